@@ -5,11 +5,12 @@ use axum::Router;
 use http::StatusCode;
 use reqwest::Client;
 use std::net::SocketAddr;
-use std::sync::{Arc, RwLock};
+use std::sync::Arc;
 use tokio::net::TcpListener;
 use tracing::{info, warn};
 use zip_static_handler::github::zip_download_branch_url;
 use zip_static_handler::handler::Handler;
+use zip_static_handler::swappable::SwappableHandler;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -28,7 +29,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         "main",
     ))
     .await?;
-    let handler = Arc::new(RwLock::new(
+    let handler = Arc::new(SwappableHandler::new(
         Handler::builder()
             .with_zip_prefix("about.programingjd.me-main/")
             .with_zip(zip)
@@ -52,7 +53,7 @@ fn is_authorized(_request: &Request) -> bool {
 }
 
 async fn update_webhook(
-    State(state): State<Arc<RwLock<Handler>>>,
+    State(state): State<Arc<SwappableHandler>>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     request: Request,
 ) -> Response {
@@ -77,21 +78,22 @@ async fn update_webhook(
             .body(().into())
             .unwrap();
     };
-    let handler = if let Ok(handler) = Handler::builder()
-        .with_zip_prefix("about.programingjd.me-main/")
-        .with_zip(zip)
-        .try_build()
+    // the unchanged files are copied from the current handler,
+    // which keeps serving requests if the new content is invalid
+    if state
+        .replace(
+            Handler::builder()
+                .with_zip_prefix("about.programingjd.me-main/")
+                .with_zip(zip),
+        )
+        .is_err()
     {
-        handler
-    } else {
         warn!("Failed to build handler.");
         return Response::builder()
             .status(StatusCode::INTERNAL_SERVER_ERROR)
             .body(().into())
             .unwrap();
-    };
-    let mut guard = state.write().unwrap();
-    *guard = handler;
+    }
     info!("Handler updated");
     Response::builder()
         .status(StatusCode::OK)
@@ -99,12 +101,8 @@ async fn update_webhook(
         .unwrap()
 }
 
-async fn static_handler(State(state): State<Arc<RwLock<Handler>>>, request: Request) -> Response {
-    if let Ok(handler) = state.read() {
-        handler.handle_axum_request(request)
-    } else {
-        Response::builder().status(500).body(().into()).unwrap()
-    }
+async fn static_handler(State(state): State<Arc<SwappableHandler>>, request: Request) -> Response {
+    state.handle_axum_request(request)
 }
 
 async fn download(url: &str) -> Result<Vec<u8>, reqwest::Error> {
@@ -0,0 +1,34 @@
+use axum::extract::{Request, State};
+use axum::response::Response;
+use axum::Router;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use zip_static_handler::dev::watch;
+use zip_static_handler::swappable::SwappableHandler;
+
+// Serves the content of the directory given as argument (or the current directory), and
+// reloads the pages opened in a browser when the files change.
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt()
+        .compact()
+        .with_env_filter("zip_static_handler=info")
+        .without_time()
+        .with_line_number(false)
+        .with_file(false)
+        .try_init()
+        .expect("could not init tracing subscriber");
+    let directory = std::env::args().nth(1).unwrap_or_else(|| ".".to_string());
+    let handler = watch(directory)?;
+    let listener = TcpListener::bind(("127.0.0.1", 8080u16)).await?;
+    axum::serve(
+        listener,
+        Router::new().fallback(static_handler).with_state(handler),
+    )
+    .await?;
+    Ok(())
+}
+
+async fn static_handler(State(state): State<Arc<SwappableHandler>>, request: Request) -> Response {
+    state.handle_axum_request(request)
+}
@@ -13,7 +13,7 @@ use zip_static_handler::handler::{Handler, HeaderSelector, HeadersAndCompression
 use zip_static_handler::http::headers::{Line, ALLOW, CACHE_CONTROL, CONTENT_TYPE};
 
 static DEFAULT_HEADERS: LazyLock<Vec<Line>> =
-    LazyLock::new(|| vec![Line::with_array_ref_value(ALLOW, b"GET, HEAD")]);
+    LazyLock::new(|| vec![Line::with_array_ref_value(ALLOW, b"GET, HEAD, OPTIONS")]);
 
 async fn download(url: &str) -> Result<Vec<u8>, reqwest::Error> {
     let response = Client::default().get(url).send().await?;
@@ -12,7 +12,7 @@ use tokio::time::timeout;
 use zip_static_handler::github::zip_download_branch_url;
 use zip_static_handler::handler::Handler;
 use zip_static_handler::http::response::StatusCode;
-use zip_static_handler::types::DEFAULT_HEADERS;
+use zip_static_handler::types::ERROR_HEADERS;
 
 #[tokio::main]
 async fn main() {
@@ -87,25 +87,18 @@ async fn request_loop(
                 }
             }
             _ => {
-                let path = String::from_utf8_lossy(path);
-                if if let Some(entry) = handler.accept(path.as_ref()) {
-                    handler
-                        .handle_path(
-                            &method,
-                            entry,
-                            &parser,
-                            &mut reader,
-                            &mut writer,
-                            &mut buffer2,
-                            &mut buffer3,
-                        )
-                        .await
-                } else {
-                    handler
-                        .handle_not_found(&method, &parser, &mut reader, &mut writer, &mut buffer2)
-                        .await
-                }
-                .is_some()
+                if handler
+                    .handle_request(
+                        &method,
+                        path,
+                        &parser,
+                        &mut reader,
+                        &mut writer,
+                        &mut buffer2,
+                        &mut buffer3,
+                    )
+                    .await
+                    .is_some()
                 {
                     if writer.flush().await.is_err() {
                         break;
@@ -130,25 +123,25 @@ async fn handle_healthcheck<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin>(
 ) -> Option<()> {
     if *method != Method::Head {
         Handler::write_status_line(writer, StatusCode::MethodNotAllowed).await?;
-        Handler::write_headers(writer, DEFAULT_HEADERS.iter(), true).await?;
+        Handler::write_headers(writer, ERROR_HEADERS.iter(), true).await?;
         None
     } else {
         match parser.parse_headers(reader, buffer).await {
             Err(Error::ReadTimeout) => None,
             Err(Error::RequestTooLarge) => {
                 Handler::write_status_line(writer, StatusCode::RequestTooLarge).await?;
-                Handler::write_headers(writer, DEFAULT_HEADERS.iter(), true).await?;
+                Handler::write_headers(writer, ERROR_HEADERS.iter(), true).await?;
                 None
             }
             Err(Error::BadRequest) => {
                 Handler::write_status_line(writer, StatusCode::BadRequest).await?;
-                Handler::write_headers(writer, DEFAULT_HEADERS.iter(), true).await?;
+                Handler::write_headers(writer, ERROR_HEADERS.iter(), true).await?;
                 None
             }
             Err(_) => unimplemented!(),
             Ok(_) => {
                 Handler::write_status_line(writer, StatusCode::OK).await?;
-                Handler::write_headers(writer, DEFAULT_HEADERS.iter(), true).await?;
+                Handler::write_headers(writer, ERROR_HEADERS.iter(), true).await?;
                 Some(())
             }
         }
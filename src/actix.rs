@@ -2,6 +2,8 @@ use crate::handler::Handler;
 use crate::http::headers::Line;
 use crate::http::request::Request;
 use crate::http::response::StatusCode;
+#[cfg(feature = "swappable")]
+use crate::swappable::SwappableHandler;
 use actix_web::body::EitherBody;
 use actix_web::{HttpRequest, HttpResponse};
 use bytes::Bytes;
@@ -16,6 +18,16 @@ impl Handler {
     }
 }
 
+#[cfg(feature = "swappable")]
+impl SwappableHandler {
+    pub fn handle_actix_request(
+        &self,
+        request: HttpRequest,
+    ) -> HttpResponse<EitherBody<Bytes, ()>> {
+        self.load().handle_actix_request(request)
+    }
+}
+
 struct RequestAdapter {
     inner: HttpRequest,
 }
@@ -29,6 +41,10 @@ impl Request<HttpResponse<EitherBody<Bytes, ()>>> for RequestAdapter {
         self.inner.uri().path().as_bytes()
     }
 
+    fn query(&self) -> &[u8] {
+        self.inner.uri().query().unwrap_or_default().as_bytes()
+    }
+
     fn first_header_value(&self, key: &'static [u8]) -> Option<&[u8]> {
         from_utf8(key)
             .ok()
@@ -44,7 +60,7 @@ impl Request<HttpResponse<EitherBody<Bytes, ()>>> for RequestAdapter {
         let code: u16 = code.into();
         let mut builder = HttpResponse::build(actix_web::http::StatusCode::from_u16(code).unwrap());
         headers.for_each(|line| {
-            builder.append_header((line.key, line.value.as_ref()));
+            builder.append_header((line.name(), line.value.as_ref()));
         });
         let body = body
             .map(|body| EitherBody::Left { body })
@@ -0,0 +1,391 @@
+use crate::base64;
+use crate::errors::Result;
+use crate::http::OwnedOrStatic;
+use crate::http::headers::{CACHE_CONTROL, Line, WWW_AUTHENTICATE};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::sync::{Arc, Mutex};
+
+const PBKDF2_SHA256: &str = "$pbkdf2-sha256$";
+
+/// Password hash for `AuthPolicy::with_user`, in the PHC string format:
+/// `$pbkdf2-sha256$i=<iterations>$<salt>$<hash>`, with the salt and the 32 bytes PBKDF2-HMAC-SHA256
+/// hash in base64 (without padding).
+/// The salt should be random (`openssl rand 16`), and the iteration count as high as the cost of
+/// a request allows (600000 is the current OWASP recommendation).
+pub fn hash_password(password: &str, salt: &[u8], iterations: u32) -> String {
+    let hash = pbkdf2_sha256(password.as_bytes(), salt, iterations);
+    format!(
+        "{PBKDF2_SHA256}i={iterations}${}${}",
+        base64::encode(salt).trim_end_matches('='),
+        base64::encode(&hash).trim_end_matches('=')
+    )
+}
+
+#[derive(Clone, Debug)]
+struct PasswordHash {
+    iterations: u32,
+    salt: Vec<u8>,
+    hash: [u8; 32],
+}
+
+impl PasswordHash {
+    fn parse(phc: &str) -> Option<Self> {
+        let mut parts = phc.trim().strip_prefix(PBKDF2_SHA256)?.split('$');
+        let iterations = parts.next()?.strip_prefix("i=")?.parse().ok()?;
+        let salt = base64::decode(parts.next()?.as_bytes())?;
+        let hash = base64::decode(parts.next()?.as_bytes())?.try_into().ok()?;
+        if iterations == 0 || salt.is_empty() || parts.next().is_some() {
+            return None;
+        }
+        Some(Self {
+            iterations,
+            salt,
+            hash,
+        })
+    }
+
+    fn verify(&self, password: &[u8]) -> bool {
+        constant_time_eq(
+            &pbkdf2_sha256(password, &self.salt, self.iterations),
+            &self.hash,
+        )
+    }
+}
+
+/// Credentials accepted for the paths under a prefix.
+/// Requests without valid credentials get a 401 response with a challenge for each of the
+/// accepted schemes.
+#[derive(Clone, Debug)]
+pub struct AuthPolicy {
+    realm: String,
+    // username and password hash (None if the hash isn't valid)
+    users: Vec<(String, Option<PasswordHash>)>,
+    // sha-256 hashes of the bearer tokens
+    tokens: Vec<[u8; 32]>,
+    // sha-256 hashes of the basic credentials that were verified, so that the password hash
+    // isn't computed again for each request
+    verified: Arc<Mutex<Vec<[u8; 32]>>>,
+}
+
+impl AuthPolicy {
+    pub fn new(realm: impl Into<String>) -> Self {
+        Self {
+            realm: realm.into(),
+            users: vec![],
+            tokens: vec![],
+            verified: Arc::default(),
+        }
+    }
+    /// Accepts HTTP Basic authentication for the user, given the hash of its password (see
+    /// `hash_password` for the format).
+    pub fn with_user(
+        mut self,
+        username: impl Into<String>,
+        password_hash: impl AsRef<str>,
+    ) -> Self {
+        self.users
+            .push((username.into(), PasswordHash::parse(password_hash.as_ref())));
+        self
+    }
+    /// Accepts Bearer authentication with the given static token.
+    /// Tokens are compared by their sha-256 hash, so they should be long and random.
+    pub fn with_bearer_token(mut self, token: impl AsRef<[u8]>) -> Self {
+        self.tokens.push(Sha256::digest(token.as_ref()).into());
+        self
+    }
+
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.users.is_empty() && self.tokens.is_empty() {
+            return Err(format!("no credentials for realm: {}", self.realm).into());
+        }
+        for (username, password) in &self.users {
+            if username.is_empty() || username.contains(':') {
+                return Err(format!("invalid username: {username}").into());
+            }
+            if password.is_none() {
+                return Err(format!("invalid password hash for user: {username}").into());
+            }
+        }
+        Ok(())
+    }
+
+    // Whether the value of the authorization request header has valid credentials.
+    // All the credentials are compared, in constant time, to avoid leaking which one matched.
+    pub(crate) fn is_authorized(&self, authorization: Option<&[u8]>) -> bool {
+        let Some(authorization) = authorization else {
+            return false;
+        };
+        let (scheme, credentials) = match authorization.iter().position(|&b| b == b' ') {
+            Some(i) => (&authorization[..i], authorization[i + 1..].trim_ascii()),
+            None => return false,
+        };
+        if scheme.eq_ignore_ascii_case(b"basic") {
+            let Some(decoded) = base64::decode(credentials) else {
+                return false;
+            };
+            let Some(i) = decoded.iter().position(|&b| b == b':') else {
+                return false;
+            };
+            let credentials: [u8; 32] = Sha256::digest(&decoded).into();
+            let is_verified = |verified: &[[u8; 32]]| {
+                verified.iter().fold(false, |authorized, it| {
+                    authorized | constant_time_eq(it, &credentials)
+                })
+            };
+            if is_verified(&self.verified.lock().unwrap()) {
+                return true;
+            }
+            let (username, password) = (&decoded[..i], &decoded[i + 1..]);
+            let user = self.users.iter().fold(None, |user, (name, hash)| {
+                if constant_time_eq(name.as_bytes(), username) {
+                    hash.as_ref()
+                } else {
+                    user
+                }
+            });
+            // the hash of an unknown user is still computed, to not leak which users exist
+            let authorized = match user {
+                Some(hash) => hash.verify(password),
+                None => {
+                    if let Some((_, Some(hash))) = self.users.first() {
+                        hash.verify(password);
+                    }
+                    false
+                }
+            };
+            if authorized {
+                let mut verified = self.verified.lock().unwrap();
+                if !is_verified(&verified) {
+                    verified.push(credentials);
+                }
+            }
+            authorized
+        } else if scheme.eq_ignore_ascii_case(b"bearer") {
+            let hash: [u8; 32] = Sha256::digest(credentials).into();
+            self.tokens.iter().fold(false, |authorized, expected| {
+                authorized | constant_time_eq(expected, &hash)
+            })
+        } else {
+            false
+        }
+    }
+
+    // www-authenticate headers of the 401 response.
+    pub(crate) fn challenges(&self) -> Vec<Line> {
+        let realm = self.realm.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut headers = vec![];
+        if !self.users.is_empty() {
+            headers.push(Line::with_owned_value(
+                WWW_AUTHENTICATE,
+                format!("Basic realm=\"{realm}\", charset=\"UTF-8\"").into_bytes(),
+            ));
+        }
+        if !self.tokens.is_empty() {
+            headers.push(Line::with_owned_value(
+                WWW_AUTHENTICATE,
+                format!("Bearer realm=\"{realm}\"").into_bytes(),
+            ));
+        }
+        headers
+    }
+}
+
+// Auth policies by path prefix.
+#[derive(Default)]
+pub(crate) struct AuthPolicies(Vec<(String, AuthPolicy)>);
+
+impl AuthPolicies {
+    pub(crate) fn add(&mut self, prefix: String, policy: AuthPolicy) {
+        self.0.retain(|(it, _)| *it != prefix);
+        self.0.push((prefix, policy));
+        // the longest prefix takes precedence
+        self.0.sort_by_key(|(prefix, _)| Reverse(prefix.len()));
+    }
+
+    pub(crate) fn find(&self, path: &str) -> Option<&AuthPolicy> {
+        self.0
+            .iter()
+            .find(|(prefix, _)| {
+                prefix.is_empty()
+                    || path
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .map(|(_, policy)| policy)
+    }
+
+    pub(crate) fn validate(&self) -> Result<()> {
+        self.0.iter().try_for_each(|(_, policy)| policy.validate())
+    }
+}
+
+// Makes the cache-control header of a protected entry private, so that shared caches don't
+// serve it to other clients.
+pub(crate) fn private_cache_control(headers: &mut [Line]) {
+    if let Some(line) = headers.iter_mut().find(|it| it.key == CACHE_CONTROL) {
+        let directives = String::from_utf8_lossy(line.value.as_ref())
+            .split(',')
+            .map(str::trim)
+            .filter(|it| !it.eq_ignore_ascii_case("public"))
+            .collect::<Vec<_>>()
+            .join(",");
+        let value = if directives.is_empty() {
+            "private".to_string()
+        } else {
+            format!("private,{directives}")
+        };
+        line.value = OwnedOrStatic::Owned(value.into_bytes());
+    }
+}
+
+// PBKDF2-HMAC-SHA256 (RFC 8018), for a 32 bytes key (a single block).
+fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    // hmac accepts keys of any length
+    let prf = Hmac::<Sha256>::new_from_slice(password).unwrap();
+    let mut mac = prf.clone();
+    mac.update(salt);
+    mac.update(&1u32.to_be_bytes());
+    let mut block = mac.finalize().into_bytes();
+    let mut key: [u8; 32] = block.into();
+    for _ in 1..iterations {
+        let mut mac = prf.clone();
+        mac.update(&block);
+        block = mac.finalize().into_bytes();
+        key.iter_mut().zip(block.iter()).for_each(|(k, b)| *k ^= b);
+    }
+    key
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // hash_password("secret", b"zip-static-salt", 1000)
+    const SECRET_HASH: &str =
+        "$pbkdf2-sha256$i=1000$emlwLXN0YXRpYy1zYWx0$cx2c6+CZ1tlrvztMZWC4g7XZ65WRL6Jj24xX8YHdPt0";
+
+    fn basic(credentials: &str) -> Vec<u8> {
+        format!("Basic {}", base64::encode(credentials.as_bytes())).into_bytes()
+    }
+
+    #[test]
+    fn pbkdf2() {
+        // common PBKDF2-HMAC-SHA256 test vector (password, salt, 4096 iterations)
+        assert_eq!(
+            pbkdf2_sha256(b"password", b"salt", 4096),
+            [
+                0xc5, 0xe4, 0x78, 0xd5, 0x92, 0x88, 0xc8, 0x41, 0xaa, 0x53, 0x0d, 0xb6, 0x84, 0x5c,
+                0x4c, 0x8d, 0x96, 0x28, 0x93, 0xa0, 0x01, 0xce, 0x4e, 0x11, 0xa4, 0x96, 0x38, 0x73,
+                0xaa, 0x98, 0x13, 0x4a
+            ]
+        );
+        assert_eq!(
+            hash_password("secret", b"zip-static-salt", 1000),
+            SECRET_HASH
+        );
+    }
+
+    #[test]
+    fn basic_auth() {
+        let policy = AuthPolicy::new("internal").with_user("admin", SECRET_HASH);
+        assert!(policy.validate().is_ok());
+        assert!(policy.is_authorized(Some(&basic("admin:secret"))));
+        assert!(policy.is_authorized(Some(b"basic  YWRtaW46c2VjcmV0")));
+        assert_eq!(policy.verified.lock().unwrap().len(), 1);
+        assert!(!policy.is_authorized(Some(&basic("admin:Secret"))));
+        assert!(!policy.is_authorized(Some(&basic("root:secret"))));
+        assert!(!policy.is_authorized(Some(&basic("admin"))));
+        assert!(!policy.is_authorized(Some(b"Basic !!")));
+        assert!(!policy.is_authorized(Some(b"Bearer secret")));
+        assert!(!policy.is_authorized(None));
+        let challenges = policy.challenges();
+        assert_eq!(challenges.len(), 1);
+        assert_eq!(
+            challenges[0].value.as_ref(),
+            b"Basic realm=\"internal\", charset=\"UTF-8\""
+        );
+    }
+
+    #[test]
+    fn bearer_auth() {
+        let policy = AuthPolicy::new("api \"v1\"").with_bearer_token("token");
+        assert!(policy.validate().is_ok());
+        assert!(policy.is_authorized(Some(b"Bearer token")));
+        assert!(!policy.is_authorized(Some(b"Bearer other")));
+        assert!(!policy.is_authorized(Some(&basic("a:token"))));
+        assert_eq!(
+            policy.challenges()[0].value.as_ref(),
+            b"Bearer realm=\"api \\\"v1\\\"\""
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(AuthPolicy::new("a").validate().is_err());
+        for hash in [
+            "secret",
+            // plain sha-256 hashes are only used for the bearer tokens
+            "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b",
+            "$pbkdf2-sha256$i=0$emlwLXN0YXRpYy1zYWx0$cx2c6+CZ1tlrvztMZWC4g7XZ65WRL6Jj24xX8YHdPt0",
+            "$pbkdf2-sha256$i=1000$$cx2c6+CZ1tlrvztMZWC4g7XZ65WRL6Jj24xX8YHdPt0",
+            "$pbkdf2-sha256$i=1000$emlwLXN0YXRpYy1zYWx0$cx2c6+CZ1tlrvztMZWC4g7XZ65WRL6Jj24xX",
+            "$pbkdf2-sha512$i=1000$emlwLXN0YXRpYy1zYWx0$cx2c6+CZ1tlrvztMZWC4g7XZ65WRL6Jj24xX8YHdPt0",
+        ] {
+            assert!(
+                AuthPolicy::new("a")
+                    .with_user("admin", hash)
+                    .validate()
+                    .is_err(),
+                "{hash}"
+            );
+        }
+        assert!(
+            AuthPolicy::new("a")
+                .with_user("a:b", SECRET_HASH)
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn prefixes() {
+        let mut policies = AuthPolicies::default();
+        policies.add("/internal".to_string(), AuthPolicy::new("a"));
+        policies.add("/internal/public".to_string(), AuthPolicy::new("b"));
+        assert_eq!(
+            policies.find("/internal").map(|it| it.realm.as_str()),
+            Some("a")
+        );
+        assert_eq!(
+            policies.find("/internal/a").map(|it| it.realm.as_str()),
+            Some("a")
+        );
+        assert_eq!(
+            policies
+                .find("/internal/public/a")
+                .map(|it| it.realm.as_str()),
+            Some("b")
+        );
+        assert!(policies.find("/internals").is_none());
+        assert!(policies.find("/").is_none());
+    }
+
+    #[test]
+    fn cache_control() {
+        let mut headers = vec![Line::with_slice_value(
+            CACHE_CONTROL,
+            b"public,max-age=3600,stale-if-error=3600",
+        )];
+        private_cache_control(&mut headers);
+        assert_eq!(
+            headers[0].value.as_ref(),
+            b"private,max-age=3600,stale-if-error=3600"
+        );
+    }
+}
@@ -2,6 +2,8 @@ use crate::handler::Handler;
 use crate::http::headers::Line;
 use crate::http::request::Request;
 use crate::http::response::StatusCode;
+#[cfg(feature = "swappable")]
+use crate::swappable::SwappableHandler;
 use axum_core::response::IntoResponse;
 use bytes::Bytes;
 use http::{HeaderMap, HeaderName, HeaderValue};
@@ -17,6 +19,13 @@ impl Handler {
     }
 }
 
+#[cfg(feature = "swappable")]
+impl SwappableHandler {
+    pub fn handle_axum_request(&self, request: AxumRequest) -> AxumResponse {
+        self.load().handle_axum_request(request)
+    }
+}
+
 struct RequestAdapter {
     inner: AxumRequest,
 }
@@ -30,6 +39,10 @@ impl Request<AxumResponse> for RequestAdapter {
         self.inner.uri().path().as_bytes()
     }
 
+    fn query(&self) -> &[u8] {
+        self.inner.uri().query().unwrap_or_default().as_bytes()
+    }
+
     fn first_header_value(&self, key: &'static [u8]) -> Option<&[u8]> {
         from_utf8(key)
             .ok()
@@ -45,7 +58,7 @@ impl Request<AxumResponse> for RequestAdapter {
         let status_code: HttpStatusCode = HttpStatusCode::from_u16(code.into()).unwrap();
         let mut map = HeaderMap::new();
         headers.for_each(|line| {
-            if let Ok(name) = HeaderName::from_bytes(line.key)
+            if let Ok(name) = HeaderName::from_bytes(line.name())
                 && let Ok(value) = HeaderValue::from_bytes(line.value.as_ref())
             {
                 map.append(name, value);
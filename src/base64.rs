@@ -0,0 +1,112 @@
+// decoding and url-safe encoding are only used by the authentication and the signed urls
+#![cfg_attr(not(feature = "auth"), allow(dead_code))]
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const URL_SAFE_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+// Standard base64 encoding, with padding.
+pub(crate) fn encode(bytes: &[u8]) -> String {
+    encode_with(bytes, ALPHABET, true)
+}
+
+// Decodes standard base64, with or without padding.
+pub(crate) fn decode(encoded: &[u8]) -> Option<Vec<u8>> {
+    decode_with(encoded, ALPHABET)
+}
+
+// Url-safe base64 encoding, without padding.
+pub(crate) fn encode_url_safe(bytes: &[u8]) -> String {
+    encode_with(bytes, URL_SAFE_ALPHABET, false)
+}
+
+// Decodes url-safe base64, with or without padding.
+pub(crate) fn decode_url_safe(encoded: &[u8]) -> Option<Vec<u8>> {
+    decode_with(encoded, URL_SAFE_ALPHABET)
+}
+
+fn encode_with(bytes: &[u8], alphabet: &[u8; 64], padding: bool) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(alphabet[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else if padding {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn decode_with(encoded: &[u8], alphabet: &[u8; 64]) -> Option<Vec<u8>> {
+    let encoded = match encoded {
+        [rest @ .., b'=', b'='] | [rest @ .., b'='] if encoded.len().is_multiple_of(4) => rest,
+        _ => encoded,
+    };
+    if encoded.len() % 4 == 1 {
+        return None;
+    }
+    let mut decoded = Vec::with_capacity(encoded.len() * 3 / 4);
+    for chunk in encoded.chunks(4) {
+        let n = chunk.iter().enumerate().try_fold(0u32, |n, (i, &b)| {
+            let value = alphabet.iter().position(|&it| it == b)? as u32;
+            Some(n | value << (18 - 6 * i))
+        })?;
+        for i in 0..chunk.len() - 1 {
+            decoded.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding() {
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"f"), "Zg==");
+        assert_eq!(encode(b"fo"), "Zm8=");
+        assert_eq!(encode(b"foo"), "Zm9v");
+        assert_eq!(encode(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn decoding() {
+        for value in [
+            b"".as_slice(),
+            b"f",
+            b"fo",
+            b"foo",
+            b"foob",
+            b"\xff\x00\xfe",
+        ] {
+            let encoded = encode(value);
+            assert_eq!(decode(encoded.as_bytes()).as_deref(), Some(value));
+            let unpadded = encoded.trim_end_matches('=');
+            assert_eq!(decode(unpadded.as_bytes()).as_deref(), Some(value));
+        }
+        assert_eq!(decode(b"Zg=").as_deref(), None);
+        assert_eq!(decode(b"Z"), None);
+        assert_eq!(decode(b"Zm9v!"), None);
+        assert_eq!(decode(b"Zm=v"), None);
+    }
+
+    #[test]
+    fn url_safe() {
+        assert_eq!(encode(b"\xfb\xff\xbf"), "+/+/");
+        assert_eq!(encode_url_safe(b"\xfb\xff\xbf"), "-_-_");
+        assert_eq!(encode_url_safe(b"fo"), "Zm8");
+        assert_eq!(
+            decode_url_safe(b"-_-_").as_deref(),
+            Some(b"\xfb\xff\xbf".as_slice())
+        );
+        assert_eq!(decode_url_safe(b"+/+/"), None);
+    }
+}
@@ -1,14 +1,45 @@
+#[cfg(feature = "auth")]
+use crate::auth::{private_cache_control, AuthPolicies, AuthPolicy};
+use crate::compression::compress_all;
+use crate::cors::{CorsPolicies, CorsPolicy};
+#[cfg(feature = "csp")]
+use crate::csp::add_csp_hashes;
+use crate::error_pages::{ErrorPages, ErrorStatus};
 use crate::errors::Result;
-use crate::handler::{Entry, Handler, HeaderSelector, HeadersAndCompression};
+use crate::header_rules::HeaderRules;
+use crate::handler::{encoded_content, Entry, Handler, HeaderSelector, HeadersAndCompression};
 use crate::http::headers::{Line, CONTENT_LENGTH, LOCATION};
-use crate::types::DefaultHeaderSelector;
+use crate::path::{entry_route, filename, html_name, is_hidden, relative_name};
+use crate::redirects::Redirects;
+#[cfg(feature = "auth")]
+use crate::signed_urls::SignedUrls;
+use crate::source::{Directory, Files, Layers, Source, Tar};
+use crate::spa::{SpaFallback, SpaFallbacks};
+use crate::types::{DefaultHeaderSelector, SecurityHeaders};
+use bytes::Bytes;
 use std::borrow::Borrow;
 use std::collections::HashMap;
-use std::io::Cursor;
 use std::marker::PhantomData;
+use std::path::Path;
+use std::thread::available_parallelism;
 use tracing::{info, trace};
-use zip_structs::zip_central_directory::ZipCDEntry;
-use zip_structs::zip_eocd::ZipEOCD;
+
+// Names of the redirect and header rules files, at the root of the archive.
+const REDIRECTS: &str = "_redirects";
+const HEADERS: &str = "_headers";
+
+// Content of a file at the root of the archive (relative to the zip prefix).
+fn root_file(files: &mut dyn Files, zip_prefix: &str, filename: &str) -> Result<Option<Bytes>> {
+    let Some(name) = files
+        .names()
+        .iter()
+        .find(|it| it.starts_with(zip_prefix) && relative_name(zip_prefix, it) == filename)
+        .cloned()
+    else {
+        return Ok(None);
+    };
+    Ok(files.read(&name)?.map(|it| it.content))
+}
 
 pub trait ZipPrefix {
     fn zip_prefix(self) -> Option<String>;
@@ -85,6 +116,172 @@ impl Content for NoContent {}
 impl WithoutContent for NoContent {}
 impl<T: Borrow<[u8]>> Content for T {}
 
+/// Brotli encoder parameters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BrotliParams {
+    /// Compression quality, from 0 (fastest) to 11 (smallest output).
+    pub quality: u8,
+    /// Base 2 logarithm of the sliding window size, from 10 to 24.
+    pub window: u8,
+}
+
+impl Default for BrotliParams {
+    fn default() -> Self {
+        Self {
+            quality: 11,
+            window: 22,
+        }
+    }
+}
+
+pub trait CompressionSelector {
+    fn brotli_params(&self, filename: &str, extension: &str, len: usize) -> BrotliParams;
+}
+
+/// Brotli parameters selected by file extension first, then by file size.
+pub struct CompressionRules {
+    default: BrotliParams,
+    extensions: Vec<(String, BrotliParams)>,
+    sizes: Vec<(usize, BrotliParams)>,
+}
+
+impl CompressionRules {
+    pub fn new(default: BrotliParams) -> Self {
+        Self {
+            default,
+            extensions: vec![],
+            sizes: vec![],
+        }
+    }
+    /// Parameters for files with the given extension, regardless of their size.
+    pub fn with_extension(mut self, extension: impl Into<String>, params: BrotliParams) -> Self {
+        self.extensions.push((extension.into(), params));
+        self
+    }
+    /// Parameters for files of at least `min_len` bytes.
+    /// When several size rules apply, the one with the largest `min_len` is used.
+    pub fn with_min_size(mut self, min_len: usize, params: BrotliParams) -> Self {
+        self.sizes.push((min_len, params));
+        self
+    }
+}
+
+impl Default for CompressionRules {
+    fn default() -> Self {
+        Self::new(BrotliParams::default())
+    }
+}
+
+impl CompressionSelector for CompressionRules {
+    fn brotli_params(&self, _filename: &str, extension: &str, len: usize) -> BrotliParams {
+        self.extensions
+            .iter()
+            .find(|(it, _)| it == extension)
+            .map(|(_, params)| *params)
+            .or_else(|| {
+                self.sizes
+                    .iter()
+                    .filter(|(min_len, _)| len >= *min_len)
+                    .max_by_key(|(min_len, _)| *min_len)
+                    .map(|(_, params)| *params)
+            })
+            .unwrap_or(self.default)
+    }
+}
+
+/// Entries with a hidden (dot) file or directory in their path that should be served.
+/// Rules are path prefixes relative to the zip prefix (`.well-known`), and denied prefixes take
+/// precedence over allowed ones.
+#[derive(Clone, Debug)]
+pub struct HiddenPaths {
+    allowed: Vec<String>,
+    denied: Vec<String>,
+}
+
+impl HiddenPaths {
+    /// No hidden entry is served.
+    pub fn new() -> Self {
+        Self {
+            allowed: vec![],
+            denied: vec![],
+        }
+    }
+    /// Serves the hidden entries under the given prefix (an empty prefix allows every entry).
+    pub fn allow(mut self, prefix: impl Into<String>) -> Self {
+        self.allowed.push(prefix.into().trim_matches('/').to_string());
+        self
+    }
+    /// Skips the hidden entries under the given prefix, even if they are allowed.
+    pub fn deny(mut self, prefix: impl Into<String>) -> Self {
+        self.denied.push(prefix.into().trim_matches('/').to_string());
+        self
+    }
+    pub(crate) fn is_allowed(&self, relative_name: &str) -> bool {
+        let matches = |prefix: &String| is_under(relative_name, prefix);
+        self.allowed.iter().any(matches) && !self.denied.iter().any(matches)
+    }
+    // Whether entries under the directory may be served: the directory is allowed or contains an
+    // allowed prefix, and it isn't denied.
+    pub(crate) fn is_allowed_directory(&self, relative_name: &str) -> bool {
+        self.allowed
+            .iter()
+            .any(|it| is_under(relative_name, it) || is_under(it, relative_name))
+            && !self.denied.iter().any(|it| is_under(relative_name, it))
+    }
+}
+
+// Whether the name is the prefix or is under it (an empty prefix contains every name).
+fn is_under(name: &str, prefix: &str) -> bool {
+    prefix.is_empty()
+        || name
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+impl Default for HiddenPaths {
+    /// Only `.well-known` is served.
+    fn default() -> Self {
+        Self::new().allow(".well-known")
+    }
+}
+
+/// How the urls of html pages are formed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UrlStyle {
+    /// Pages are served without extension and without trailing slash (`/a/b` for `a/b.html` and
+    /// `/docs` for `docs/index.html`), and the paths with a trailing slash are redirected.
+    #[default]
+    Clean,
+    /// Pages are served at their file name (`/a/b.html`), and directory indices at the directory
+    /// path with a trailing slash (`/docs/` for `docs/index.html`).
+    Extension,
+    /// Same as `Clean`, with the `.html` and `index.html` forms (`/a/b.html` and
+    /// `/docs/index.html`) also redirected.
+    CleanWithRedirects,
+}
+
+#[derive(Default)]
+struct Settings {
+    compression_selector: Option<Box<dyn CompressionSelector>>,
+    compression_threads: usize,
+    cors: CorsPolicies,
+    #[cfg(feature = "auth")]
+    auth: AuthPolicies,
+    #[cfg(feature = "auth")]
+    signed_urls: SignedUrls,
+    error_pages: Vec<(String, String)>,
+    spa: SpaFallbacks,
+    hidden_paths: HiddenPaths,
+    url_style: UrlStyle,
+    canonical_redirects: bool,
+    #[cfg(feature = "csp")]
+    csp_hashes: bool,
+    security_headers: SecurityHeaders,
+    // version of the handler in development mode
+    #[cfg(feature = "dev")]
+    dev: Option<u64>,
+}
+
 pub struct Builder<
     'a,
     'b,
@@ -101,6 +298,7 @@ pub struct Builder<
     header_selector: H,
     diff: D,
     content: B,
+    settings: Settings,
 }
 
 impl Handler {
@@ -113,6 +311,10 @@ impl Handler {
             header_selector: (),
             diff: (),
             content: NoContent,
+            settings: Settings {
+                compression_threads: 1,
+                ..Default::default()
+            },
         }
     }
 }
@@ -136,6 +338,7 @@ impl<
             header_selector: self.header_selector,
             diff: self.diff,
             content: self.content,
+            settings: self.settings,
         }
     }
 }
@@ -162,11 +365,12 @@ impl<
             header_selector: self.header_selector,
             diff: self.diff,
             content: self.content,
+            settings: self.settings,
         }
     }
 }
 
-fn sanitize_prefix(prefix: String) -> String {
+pub(crate) fn sanitize_prefix(prefix: String) -> String {
     let prefix = if prefix.starts_with('/') {
         prefix
     } else {
@@ -201,6 +405,7 @@ impl<
             header_selector,
             diff: self.diff,
             content: self.content,
+            settings: self.settings,
         }
     }
 }
@@ -224,6 +429,7 @@ impl<
             header_selector: self.header_selector,
             diff,
             content: self.content,
+            settings: self.settings,
         }
     }
 }
@@ -239,6 +445,30 @@ impl<
     > Builder<'a, 'b, Z, R, H, D, B>
 {
     pub fn with_zip<T: Borrow<[u8]>>(self, content: T) -> Builder<'a, 'b, Z, R, H, D, T> {
+        self.with_content(content)
+    }
+    /// Uses a tar archive as the content, with the same rules as the entries of a zip archive.
+    /// Gzip-compressed archives (`.tar.gz`) are decompressed first.
+    pub fn with_tar<T: Borrow<[u8]>>(self, content: T) -> Builder<'a, 'b, Z, R, H, D, Tar<T>> {
+        self.with_content(Tar::new(content))
+    }
+    /// Uses layers of archives or directories as the content, where the files of a layer replace
+    /// the files with the same name in the layers below (see `Layers`).
+    /// `Handler::layer` returns the layer that the entry at a path comes from.
+    pub fn with_layers<'c>(self, layers: Layers<'c>) -> Builder<'a, 'b, Z, R, H, D, Layers<'c>> {
+        self.with_content(layers)
+    }
+    /// Uses the files of a directory as the content, with the same rules as the entries of a zip
+    /// archive.
+    /// The files are read once, when the handler is built.
+    pub fn with_directory(
+        self,
+        path: impl AsRef<Path>,
+    ) -> Builder<'a, 'b, Z, R, H, D, Directory> {
+        self.with_content(Directory::new(path))
+    }
+
+    fn with_content<T: Content>(self, content: T) -> Builder<'a, 'b, Z, R, H, D, T> {
         Builder {
             _a: PhantomData,
             _b: PhantomData,
@@ -247,6 +477,7 @@ impl<
             header_selector: self.header_selector,
             diff: self.diff,
             content,
+            settings: self.settings,
         }
     }
 }
@@ -258,63 +489,397 @@ impl<
         R: PathPrefix,
         H: CustomHeaderSelector<'a>,
         D: Diff<'b>,
-        B: Borrow<[u8]>,
+        B: Content,
+    > Builder<'a, 'b, Z, R, H, D, B>
+{
+    /// Selects the brotli parameters used for compressing each file
+    /// (defaults to quality 11 and a 4MB window for every file).
+    pub fn with_compression_selector(
+        mut self,
+        compression_selector: impl CompressionSelector + 'static,
+    ) -> Self {
+        self.settings.compression_selector = Some(Box::new(compression_selector));
+        self
+    }
+    /// Number of threads used for compressing the files (defaults to 1).
+    /// 0 uses as many threads as the available parallelism.
+    /// The resulting handler is the same regardless of the number of threads.
+    pub fn with_compression_threads(mut self, threads: usize) -> Self {
+        self.settings.compression_threads = threads;
+        self
+    }
+    /// Cors policy for the paths under the given prefix (`/` for all paths).
+    /// The prefix is matched against the request path, including the root prefix.
+    /// When several prefixes match, the longest one is used.
+    pub fn with_cors(mut self, prefix: impl Into<String>, policy: CorsPolicy) -> Self {
+        self.settings
+            .cors
+            .add(sanitize_prefix(prefix.into()), policy);
+        self
+    }
+    /// Credentials required for the paths under the given prefix (`/` for all paths).
+    /// The prefix is matched against the request path, including the root prefix.
+    /// When several prefixes match, the longest one is used.
+    #[cfg(feature = "auth")]
+    pub fn with_auth(mut self, prefix: impl Into<String>, policy: AuthPolicy) -> Self {
+        self.settings
+            .auth
+            .add(sanitize_prefix(prefix.into()), policy);
+        self
+    }
+    /// Requires a valid signature (see `signed_urls::sign_url`) that hasn't expired for the paths
+    /// under the given prefix (`/` for all paths). Other requests get a 403 response.
+    /// The prefix is matched against the request path, including the root prefix.
+    /// When several prefixes match, the longest one is used.
+    #[cfg(feature = "auth")]
+    pub fn with_signed_urls(mut self, prefix: impl Into<String>, key: impl Into<Vec<u8>>) -> Self {
+        self.settings
+            .signed_urls
+            .add(sanitize_prefix(prefix.into()), key.into());
+        self
+    }
+    /// Hidden entries (with a dot file or directory in their path) that should be served
+    /// (defaults to `.well-known` only).
+    pub fn with_hidden_paths(mut self, hidden_paths: HiddenPaths) -> Self {
+        self.settings.hidden_paths = hidden_paths;
+        self
+    }
+    /// How the urls of html pages are formed (defaults to `UrlStyle::Clean`).
+    pub fn with_url_style(mut self, url_style: UrlStyle) -> Self {
+        self.settings.url_style = url_style;
+        self
+    }
+    /// Redirects (308) the request paths with dot segments or duplicate slashes
+    /// (`/a/./b`, `//a`) to their canonical form instead of serving them.
+    pub fn with_canonical_redirects(mut self) -> Self {
+        self.settings.canonical_redirects = true;
+        self
+    }
+    /// Security headers added by the default header selector
+    /// (defaults to `SecurityHeaders::default()`).
+    /// They are not used with a custom header selector.
+    pub fn with_security_headers(mut self, security_headers: SecurityHeaders) -> Self {
+        self.settings.security_headers = security_headers;
+        self
+    }
+    /// Replaces `'unsafe-inline'` in the content security policy of html pages with the hashes
+    /// of their inline scripts and styles.
+    #[cfg(feature = "csp")]
+    pub fn with_csp_hashes(mut self) -> Self {
+        self.settings.csp_hashes = true;
+        self
+    }
+    #[cfg(feature = "dev")]
+    pub(crate) fn with_dev_mode(mut self, version: u64) -> Self {
+        self.settings.dev = Some(version);
+        self
+    }
+    /// Serves the index page of a single page application for the missing paths under its root
+    /// that match its fallback rule.
+    pub fn with_spa_fallback(mut self, fallback: SpaFallback) -> Self {
+        self.settings.spa.add(fallback);
+        self
+    }
+    /// Name of the file used as the body of error responses for the given status
+    /// (`404`, or a status class like `4xx` or `5xx`).
+    /// The file from the closest directory of the request path is used.
+    /// `404.html` is used for 404 responses unless another file is set.
+    pub fn with_error_page(
+        mut self,
+        status: impl Into<String>,
+        filename: impl Into<String>,
+    ) -> Self {
+        self.settings
+            .error_pages
+            .push((status.into(), filename.into()));
+        self
+    }
+}
+
+impl<
+        'a,
+        'b,
+        Z: ZipPrefix,
+        R: PathPrefix,
+        H: CustomHeaderSelector<'a>,
+        D: Diff<'b>,
+        B: Source,
     > Builder<'a, 'b, Z, R, H, D, B>
 {
     pub fn try_build(self) -> Result<Handler> {
-        let bytes = self.content;
         let path_prefix = self.path_prefix.path_prefix().unwrap_or_default();
         let zip_prefix = self.zip_prefix.zip_prefix().unwrap_or_default();
         let diff = self.diff.diff();
+        let default_header_selector = DefaultHeaderSelector(&self.settings.security_headers);
         let header_selector = self
             .header_selector
             .header_selector()
-            .unwrap_or(&DefaultHeaderSelector);
+            .unwrap_or(&default_header_selector);
         trace!(path_prefix = path_prefix, zip_prefix = zip_prefix);
-        let mut cursor = Cursor::new(bytes.borrow());
-        let directory = ZipEOCD::from_reader(&mut cursor)?;
+        #[cfg(feature = "auth")]
+        {
+            self.settings.auth.validate()?;
+            self.settings.signed_urls.validate()?;
+        }
+        let hidden_paths = &self.settings.hidden_paths;
+        // hidden directories without any allowed entry (.git, node_modules/.cache) aren't walked
+        let mut files = self.content.files_skipping(&|directory| {
+            directory.starts_with(zip_prefix.as_str()) && {
+                let relative_name = relative_name(&zip_prefix, directory);
+                is_hidden(relative_name) && !hidden_paths.is_allowed_directory(relative_name)
+            }
+        })?;
+        let files = files.as_mut();
         let mut routes = HashMap::new();
-        let entries = ZipCDEntry::all_from_eocd(&mut cursor, &directory)?;
-        for entry in &entries {
-            if let Some((path, value)) = crate::handler::build_entry(
-                &mut cursor,
+        let default_compression_selector = CompressionRules::default();
+        let compression_selector = self
+            .settings
+            .compression_selector
+            .as_deref()
+            .unwrap_or(&default_compression_selector);
+        let mut error_page_names = vec![];
+        for (status, filename) in &self.settings.error_pages {
+            let status = ErrorStatus::parse(status)
+                .ok_or_else(|| format!("invalid error page status: {status}"))?;
+            error_page_names.push((status, filename.as_str()));
+        }
+        if !error_page_names
+            .iter()
+            .any(|(status, _)| *status == ErrorStatus::Code(404))
+        {
+            error_page_names.push((ErrorStatus::Code(404), "404.html"));
+        }
+        let redirects = match root_file(files, &zip_prefix, REDIRECTS)? {
+            Some(content) => Redirects::parse(std::str::from_utf8(&content)?, &path_prefix)?,
+            None => Redirects::default(),
+        };
+        let header_rules = match root_file(files, &zip_prefix, HEADERS)? {
+            Some(content) => HeaderRules::parse(std::str::from_utf8(&content)?, &path_prefix)?,
+            None => HeaderRules::default(),
+        };
+        let mut error_pages = ErrorPages::default();
+        // html page names by route, to detect pages served at the same route (a.html, a/index.html)
+        let mut names = HashMap::new();
+        // synthesized redirections, added after the entries so that they never replace one
+        let mut synthesized = vec![];
+        // layers of the routes, for layered sources
+        let mut layers = HashMap::new();
+        let mut built = vec![];
+        for name in files.names().to_vec() {
+            if let Some((path, mut value, pending)) = crate::handler::build_entry(
+                files,
                 zip_prefix.as_str(),
-                entry,
-                &entries,
+                path_prefix.as_str(),
+                &name,
                 header_selector,
+                compression_selector,
+                hidden_paths,
+                self.settings.url_style,
                 diff,
             )? {
+                let route = entry_route(
+                    self.settings.url_style,
+                    &path_prefix,
+                    &zip_prefix,
+                    &name,
+                    &path,
+                );
+                let layer = files.layer(&name);
+                let mut redirections = vec![];
                 // redir / to path without slash unless the path is just "/" and there's no prefix
-                if path.ends_with('/') && (path.len() > 1 || !path_prefix.is_empty()) {
-                    let path_without_trailing_slash = &path[..path.len() - 1];
+                if path.ends_with('/')
+                    && route == format!("{path_prefix}{}", &path[..path.len() - 1])
+                {
+                    redirections.push(format!("{path_prefix}{path}"));
+                }
+                if let Some(html_name) = html_name(&zip_prefix, &name)
+                    .filter(|_| self.settings.url_style == UrlStyle::CleanWithRedirects)
+                {
+                    redirections.push(format!("{path_prefix}/{html_name}"));
+                }
+                for from in redirections {
                     if let Some(HeadersAndCompression { mut headers, .. }) =
-                        header_selector.headers_for_extension(path_without_trailing_slash, "308")
+                        header_selector.headers_for_extension(&route, "308")
                     {
-                        let location = format!("{path_prefix}{path_without_trailing_slash}");
                         headers.push(Line::with_slice_value(CONTENT_LENGTH, b"0"));
-                        headers.push(Line::with_owned_value(LOCATION, location.into_bytes()));
+                        headers.push(Line::with_owned_value(LOCATION, route.as_bytes().to_vec()));
                         let entry = Entry {
                             headers,
                             content: None,
+                            encoded: vec![],
                             etag: None,
+                            last_modified: None,
                         };
-                        let path = format!("{path_prefix}{path}");
-                        info!(path = &path);
-                        routes.insert(path, entry);
+                        synthesized.push((from, entry, layer));
                     }
-                    let path = format!("{path_prefix}{path_without_trailing_slash}");
-                    info!(path = &path);
-                    routes.insert(path, value);
-                } else {
-                    let path = format!("{path_prefix}{path}");
-                    info!(path = &path);
-                    routes.insert(path, value);
                 }
+                if html_name(&zip_prefix, &name).is_some()
+                    && let Some(other) = names.insert(route.clone(), name.clone())
+                {
+                    return Err(format!(
+                        "route collision: {other} and {name} are both served at {route}"
+                    )
+                    .into());
+                }
+                if let Some(layer) = layer {
+                    layers.insert(route.clone(), layer);
+                }
+                for (status, _) in error_page_names
+                    .iter()
+                    .filter(|(_, it)| *it == filename(&name))
+                {
+                    let without_trailing_slash = path.trim_end_matches('/');
+                    let directory = &without_trailing_slash
+                        [..without_trailing_slash.rfind('/').map(|it| it + 1).unwrap_or(0)];
+                    trace!(error_page = &path, directory = directory);
+                    error_pages.add(format!("{path_prefix}{directory}"), *status, route.clone());
+                }
+                header_rules.apply(&route, &mut value.headers);
+                #[cfg(feature = "auth")]
+                if self.settings.auth.find(&route).is_some() {
+                    private_cache_control(&mut value.headers);
+                }
+                #[cfg(feature = "csp")]
+                if let Some(content) = value.content.as_ref().filter(|_| self.settings.csp_hashes) {
+                    add_csp_hashes(&mut value.headers, content);
+                }
+                #[cfg(feature = "dev")]
+                let pending = if self.settings.dev.is_some() {
+                    crate::dev::dev_entry(&mut value, pending, &path_prefix);
+                    vec![]
+                } else {
+                    pending
+                };
+                built.push((route, value, pending));
+            }
+        }
+        let jobs = built
+            .iter()
+            .flat_map(|(_, entry, pending)| {
+                let content = entry.content.as_deref().unwrap_or_default();
+                pending.iter().map(move |it| (content, it.compression))
+            })
+            .collect::<Vec<_>>();
+        let threads = match self.settings.compression_threads {
+            0 => available_parallelism().map(|it| it.get()).unwrap_or(1),
+            threads => threads,
+        };
+        trace!(compressions = jobs.len(), threads = threads);
+        let mut compressed = compress_all(&jobs, threads).into_iter();
+        for (path, mut value, pending) in built {
+            for it in pending {
+                value.encoded[it.position] =
+                    encoded_content(it.encoding, Bytes::from(compressed.next().unwrap()));
+            }
+            info!(path = &path);
+            routes.insert(path, value);
+        }
+        for (from, entry, layer) in synthesized {
+            if routes.contains_key(&from) {
+                trace!(redirection_skipped = &from);
+                continue;
+            }
+            info!(path = &from);
+            if let Some(layer) = layer {
+                layers.insert(from.clone(), layer);
             }
+            routes.insert(from, entry);
+        }
+        #[cfg(feature = "dev")]
+        if let Some(version) = self.settings.dev {
+            routes.extend(crate::dev::dev_routes(header_selector, &path_prefix, version));
+        }
+        if let Some(target) = redirects
+            .static_rewrites()
+            .find(|it| !routes.contains_key(*it))
+        {
+            return Err(format!("redirect target not found: {target}").into());
+        }
+        if let Some(fallback) = self
+            .settings
+            .spa
+            .iter()
+            .find(|it| !routes.contains_key(it.index()))
+        {
+            return Err(format!("spa index not found: {}", fallback.index()).into());
         }
         Ok(Handler {
             paths: routes,
             error_headers: header_selector.error_headers(),
+            cors: self.settings.cors,
+            #[cfg(feature = "auth")]
+            auth: self.settings.auth,
+            #[cfg(feature = "auth")]
+            signed_urls: self.settings.signed_urls,
+            error_pages,
+            spa: self.settings.spa,
+            canonical_redirects: self.settings.canonical_redirects,
+            redirects,
+            layers,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compression_threads() {
+        assert_eq!(Handler::builder().settings.compression_threads, 1);
+        assert_eq!(
+            Handler::builder()
+                .with_compression_threads(0)
+                .settings
+                .compression_threads,
+            0
+        );
+    }
+
+    #[test]
+    fn hidden_paths() {
+        let default = HiddenPaths::default();
+        assert!(default.is_allowed(".well-known/security.txt"));
+        assert!(!default.is_allowed(".git/config"));
+        assert!(!default.is_allowed(".well-known-not/a"));
+        assert!(!HiddenPaths::new().is_allowed(".well-known/security.txt"));
+        let custom = HiddenPaths::new().allow("").deny(".git").deny("/a/.idea/");
+        assert!(custom.is_allowed(".htaccess"));
+        assert!(custom.is_allowed(".github/workflows/ci.yml"));
+        assert!(!custom.is_allowed(".git/config"));
+        assert!(!custom.is_allowed("a/.idea/workspace.xml"));
+        assert!(default.is_allowed_directory(".well-known"));
+        assert!(!default.is_allowed_directory(".git"));
+        assert!(!custom.is_allowed_directory("a/.idea"));
+        let nested = HiddenPaths::new().allow(".config/public");
+        assert!(nested.is_allowed_directory(".config"));
+        assert!(!nested.is_allowed_directory(".cache"));
+    }
+
+    #[test]
+    fn compression_rules() {
+        let fast = BrotliParams {
+            quality: 5,
+            window: 22,
+        };
+        let large = BrotliParams {
+            quality: 9,
+            window: 24,
+        };
+        let huge = BrotliParams {
+            quality: 7,
+            window: 24,
+        };
+        let rules = CompressionRules::default()
+            .with_extension("css", fast)
+            .with_min_size(1_000_000, large)
+            .with_min_size(10_000_000, huge);
+        assert_eq!(rules.brotli_params("a.css", "css", 20_000_000), fast);
+        assert_eq!(
+            rules.brotli_params("a.js", "js", 2_000),
+            BrotliParams::default()
+        );
+        assert_eq!(rules.brotli_params("a.js", "js", 1_000_000), large);
+        assert_eq!(rules.brotli_params("a.wasm", "wasm", 10_000_000), huge);
+    }
+}
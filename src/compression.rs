@@ -1,10 +1,14 @@
+use crate::builder::BrotliParams;
 use brotli::enc::BrotliEncoderParams;
 use brotli::BrotliDecompress;
 use bytes::Bytes;
 use crc32fast::Hasher;
+use flate2::bufread::GzDecoder;
 use inflate::InflateWriter;
 use std::borrow::Cow;
 use std::io::{Cursor, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 use zip_structs::zip_local_file_header::ZipLocalFileHeader;
 
 pub(crate) fn decompress_entry(
@@ -23,9 +27,10 @@ pub(crate) fn decompress_entry(
     }
 }
 
-pub(crate) fn compress_brotli(bytes: &[u8], len: usize) -> Vec<u8> {
+pub(crate) fn compress_brotli(bytes: &[u8], len: usize, params: BrotliParams) -> Vec<u8> {
     let params = BrotliEncoderParams {
-        quality: 11,
+        quality: params.quality as i32,
+        lgwin: params.window as i32,
         ..Default::default()
     };
     let mut out = Vec::with_capacity(len + 64);
@@ -34,6 +39,87 @@ pub(crate) fn compress_brotli(bytes: &[u8], len: usize) -> Vec<u8> {
     out
 }
 
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Compression {
+    Brotli(BrotliParams),
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    fn compress(&self, bytes: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::Brotli(params) => compress_brotli(bytes, bytes.len(), *params),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => compress_zstd(bytes),
+        }
+    }
+}
+
+// Runs the compression jobs, on the given number of threads.
+// The results are in the same order as the jobs, regardless of the number of threads.
+pub(crate) fn compress_all(jobs: &[(&[u8], Compression)], threads: usize) -> Vec<Vec<u8>> {
+    let threads = threads.min(jobs.len());
+    if threads <= 1 {
+        return jobs
+            .iter()
+            .map(|(bytes, compression)| compression.compress(bytes))
+            .collect();
+    }
+    let next = AtomicUsize::new(0);
+    let mut results = thread::scope(|scope| {
+        let workers = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = vec![];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if let Some((bytes, compression)) = jobs.get(i) {
+                            results.push((i, compression.compress(bytes)));
+                        } else {
+                            break results;
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|it| it.join().expect("failed to compress"))
+            .collect::<Vec<_>>()
+    });
+    results.sort_unstable_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, it)| it).collect()
+}
+
+// Wraps a raw deflate stream into a gzip member (RFC 1952), without recompressing it.
+pub(crate) fn gzip_from_deflate(deflate: &[u8], crc32: u32, len: u32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(deflate.len() + 18);
+    // magic, compression method (deflate), flags, mtime, extra flags, os (unknown)
+    out.extend_from_slice(&[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff]);
+    out.extend_from_slice(deflate);
+    out.extend_from_slice(&crc32.to_le_bytes());
+    out.extend_from_slice(&len.to_le_bytes());
+    out
+}
+
+// Decompresses gzip data (RFC 1952). The members of concatenated gzip data (pigz, cat) are
+// decompressed one after the other, and the zero bytes padding the end are ignored.
+pub(crate) fn gunzip(mut bytes: &[u8]) -> crate::errors::Result<Vec<u8>> {
+    let mut out = vec![];
+    loop {
+        let mut decoder = GzDecoder::new(bytes);
+        decoder
+            .read_to_end(&mut out)
+            .map_err(|_| "invalid gzip data")?;
+        // the decoder only consumes the member, and leaves the rest of the data
+        bytes = decoder.into_inner();
+        if bytes.iter().all(|&b| b == 0) {
+            return Ok(out);
+        }
+    }
+}
+
 pub(crate) fn brotli_decompressed_crc32(bytes: &[u8]) -> Option<u32> {
     let mut cursor = Cursor::new(bytes);
     let mut crc32 = Crc32::default();
@@ -41,6 +127,18 @@ pub(crate) fn brotli_decompressed_crc32(bytes: &[u8]) -> Option<u32> {
     Some(crc32.finalize())
 }
 
+#[cfg(feature = "zstd")]
+pub(crate) fn compress_zstd(bytes: &[u8]) -> Vec<u8> {
+    zstd::bulk::compress(bytes, 19).expect("failed to compress")
+}
+
+#[cfg(feature = "zstd")]
+pub(crate) fn zstd_decompressed_crc32(bytes: &[u8]) -> Option<u32> {
+    let mut crc32 = Crc32::default();
+    zstd::stream::copy_decode(bytes, &mut crc32).ok()?;
+    Some(crc32.finalize())
+}
+
 fn inflate(bytes: &[u8], len: usize) -> Vec<u8> {
     let mut out = Vec::with_capacity(len);
     let mut writer = InflateWriter::new(&mut out);
@@ -0,0 +1,210 @@
+use crate::date::parse_http_date;
+use crate::handler::Entry;
+use crate::http::response::StatusCode;
+
+// Conditional request headers, evaluated in the order defined by RFC 9110 (section 13.2.2).
+pub(crate) struct Preconditions<'a> {
+    pub(crate) if_match: Option<&'a [u8]>,
+    pub(crate) if_none_match: Option<&'a [u8]>,
+    pub(crate) if_modified_since: Option<&'a [u8]>,
+    pub(crate) if_unmodified_since: Option<&'a [u8]>,
+}
+
+impl Preconditions<'_> {
+    // Returns the status that should be sent instead of the content, if any.
+    // Only GET and HEAD requests are handled, so a successful if-none-match or if-modified-since
+    // condition always means 304.
+    // The date conditions are only evaluated when the corresponding entity tag condition is absent,
+    // and invalid dates are ignored.
+    pub(crate) fn evaluate(&self, entry: &Entry) -> Option<StatusCode> {
+        let etag = entry.etag.as_deref()?.as_bytes();
+        if let Some(if_match) = self.if_match {
+            if !any_matches(if_match, etag, false) {
+                return Some(StatusCode::PreconditionFailed);
+            }
+        } else if let Some(last_modified) = entry.last_modified
+            && let Some(date) = self.if_unmodified_since.and_then(parse_http_date)
+            && last_modified > date
+        {
+            return Some(StatusCode::PreconditionFailed);
+        }
+        if let Some(if_none_match) = self.if_none_match {
+            if any_matches(if_none_match, etag, true) {
+                return Some(StatusCode::NotModified);
+            }
+        } else if let Some(last_modified) = entry.last_modified
+            && let Some(date) = self.if_modified_since.and_then(parse_http_date)
+            && last_modified <= date
+        {
+            return Some(StatusCode::NotModified);
+        }
+        None
+    }
+}
+
+// Checks an if-range header value against the entity tag (strong comparison only), or against
+// the modification time for an http date, which has to match exactly (RFC 9110 section 13.1.5).
+pub(crate) fn if_range_matches(
+    if_range: &[u8],
+    etag: Option<&str>,
+    last_modified: Option<u64>,
+) -> bool {
+    let if_range = if_range.trim_ascii();
+    if !matches!(if_range.first(), Some(b'"' | b'W')) {
+        return last_modified.is_some_and(|it| parse_http_date(if_range) == Some(it));
+    }
+    match (EntityTag::parse(if_range), etag) {
+        (Some((tag, [])), Some(etag)) => {
+            EntityTag::parse(etag.as_bytes()).is_some_and(|(etag, _)| tag.matches(&etag, false))
+        }
+        _ => false,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct EntityTag<'a> {
+    weak: bool,
+    // including the double quotes
+    opaque: &'a [u8],
+}
+
+impl<'a> EntityTag<'a> {
+    // Parses an entity tag at the start of the value and returns it with the remaining bytes.
+    fn parse(value: &'a [u8]) -> Option<(Self, &'a [u8])> {
+        let (weak, value) = match value.strip_prefix(b"W/") {
+            Some(value) => (true, value),
+            None => (false, value),
+        };
+        if value.first() != Some(&b'"') {
+            return None;
+        }
+        let end = value[1..]
+            .iter()
+            .position(|&b| b == b'"' || b < 0x21 || b == 0x7f)?
+            + 1;
+        if value[end] != b'"' {
+            return None;
+        }
+        let (opaque, rest) = value.split_at(end + 1);
+        Some((Self { weak, opaque }, rest))
+    }
+
+    fn matches(&self, other: &EntityTag, weak_comparison: bool) -> bool {
+        (weak_comparison || !self.weak && !other.weak) && self.opaque == other.opaque
+    }
+}
+
+// Checks whether an if-match or if-none-match header value matches the entity tag.
+// Invalid values never match.
+fn any_matches(value: &[u8], etag: &[u8], weak_comparison: bool) -> bool {
+    let Some((etag, _)) = EntityTag::parse(etag) else {
+        return false;
+    };
+    let value = value.trim_ascii();
+    if value == b"*" {
+        return true;
+    }
+    let mut rest = value;
+    loop {
+        rest = rest.trim_ascii_start();
+        if let Some(remaining) = rest.strip_prefix(b",") {
+            rest = remaining;
+            continue;
+        }
+        if rest.is_empty() {
+            return false;
+        }
+        let Some((tag, remaining)) = EntityTag::parse(rest) else {
+            return false;
+        };
+        if tag.matches(&etag, weak_comparison) {
+            return true;
+        }
+        rest = remaining;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETAG: &[u8] = b"\"1a2b3c\"";
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            EntityTag::parse(b"\"abc\", \"def\""),
+            Some((
+                EntityTag {
+                    weak: false,
+                    opaque: b"\"abc\""
+                },
+                b", \"def\"".as_slice()
+            ))
+        );
+        assert_eq!(
+            EntityTag::parse(b"W/\"a,b\""),
+            Some((
+                EntityTag {
+                    weak: true,
+                    opaque: b"\"a,b\""
+                },
+                b"".as_slice()
+            ))
+        );
+        assert_eq!(EntityTag::parse(b"abc"), None);
+        assert_eq!(EntityTag::parse(b"\"abc"), None);
+        assert_eq!(EntityTag::parse(b"\"a c\""), None);
+        assert_eq!(EntityTag::parse(b"w/\"abc\""), None);
+    }
+
+    #[test]
+    fn weak_comparison() {
+        assert!(any_matches(b"\"1a2b3c\"", ETAG, true));
+        assert!(any_matches(b"W/\"1a2b3c\"", ETAG, true));
+        assert!(any_matches(b"\"x\", W/\"1a2b3c\"", ETAG, true));
+        assert!(any_matches(b" \"x\" ,,\"1a2b3c\" ", ETAG, true));
+        assert!(any_matches(b"*", ETAG, true));
+        assert!(!any_matches(b"\"x\", \"y\"", ETAG, true));
+        assert!(!any_matches(b"1a2b3c", ETAG, true));
+        assert!(!any_matches(b"", ETAG, true));
+    }
+
+    #[test]
+    fn strong_comparison() {
+        assert!(any_matches(b"\"1a2b3c\"", ETAG, false));
+        assert!(any_matches(b"*", ETAG, false));
+        assert!(!any_matches(b"W/\"1a2b3c\"", ETAG, false));
+        assert!(if_range_matches(b"\"1a2b3c\"", Some("\"1a2b3c\""), None));
+        assert!(!if_range_matches(b"W/\"1a2b3c\"", Some("\"1a2b3c\""), None));
+        assert!(!if_range_matches(
+            b"\"1a2b3c\", \"x\"",
+            Some("\"1a2b3c\""),
+            None
+        ));
+        assert!(!if_range_matches(b"\"1a2b3c\"", None, None));
+    }
+
+    #[test]
+    fn if_range_date() {
+        // Sun, 06 Nov 1994 08:49:37 GMT
+        let last_modified = Some(784111777);
+        let etag = Some("\"1a2b3c\"");
+        assert!(if_range_matches(
+            b"Sun, 06 Nov 1994 08:49:37 GMT",
+            etag,
+            last_modified
+        ));
+        assert!(!if_range_matches(
+            b"Sun, 06 Nov 1994 08:49:38 GMT",
+            etag,
+            last_modified
+        ));
+        assert!(!if_range_matches(
+            b"Sun, 06 Nov 1994 08:49:37 GMT",
+            etag,
+            None
+        ));
+        assert!(!if_range_matches(b"invalid", etag, last_modified));
+    }
+}
@@ -0,0 +1,275 @@
+use crate::http::headers::{
+    ALLOW, CORS, CORS_ALLOW_CREDENTIALS, CORS_ALLOW_HEADERS, CORS_ALLOW_METHODS, CORS_MAX_AGE,
+    Line, VARY,
+};
+use crate::http::method;
+use std::cmp::Reverse;
+
+const ALLOWED_METHODS: &[u8] = b"GET, HEAD";
+const ALLOWED_METHODS_WITH_OPTIONS: &[u8] = b"GET, HEAD, OPTIONS";
+
+/// Origins allowed to fetch the content.
+#[derive(Clone, Debug)]
+pub enum AllowedOrigins {
+    /// Any origin (`Access-Control-Allow-Origin: *`, or the request origin with credentials).
+    Any,
+    /// A single origin (`https://example.com`).
+    Exact(String),
+    /// A list of origins. The request origin is sent back if it is in the list.
+    List(Vec<String>),
+}
+
+/// Cross-origin resource sharing policy.
+#[derive(Clone, Debug)]
+pub struct CorsPolicy {
+    origins: AllowedOrigins,
+    allowed_headers: Vec<String>,
+    max_age: Option<u32>,
+    credentials: bool,
+}
+
+impl CorsPolicy {
+    pub fn new(origins: AllowedOrigins) -> Self {
+        Self {
+            origins,
+            allowed_headers: vec![],
+            max_age: None,
+            credentials: false,
+        }
+    }
+    /// Request headers allowed in preflight responses (`Access-Control-Allow-Headers`).
+    pub fn with_allowed_headers<S: Into<String>>(
+        mut self,
+        headers: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.allowed_headers
+            .extend(headers.into_iter().map(Into::into));
+        self
+    }
+    /// How long the preflight response can be cached, in seconds (`Access-Control-Max-Age`).
+    pub fn with_max_age(mut self, seconds: u32) -> Self {
+        self.max_age = Some(seconds);
+        self
+    }
+    /// Allows requests with credentials (`Access-Control-Allow-Credentials: true`).
+    pub fn with_credentials(mut self) -> Self {
+        self.credentials = true;
+        self
+    }
+
+    // Value of the access-control-allow-origin header, if the origin is allowed.
+    fn allow_origin(&self, origin: Option<&[u8]>) -> Option<Vec<u8>> {
+        match &self.origins {
+            AllowedOrigins::Any if !self.credentials => Some(b"*".to_vec()),
+            AllowedOrigins::Any => origin.map(|it| it.to_vec()),
+            AllowedOrigins::Exact(allowed) => Some(allowed.as_bytes().to_vec()),
+            AllowedOrigins::List(allowed) => origin
+                .filter(|&origin| allowed.iter().any(|it| it.as_bytes() == origin))
+                .map(|it| it.to_vec()),
+        }
+    }
+
+    // Whether the allowed origin depends on the origin request header.
+    fn reflects_origin(&self) -> bool {
+        match self.origins {
+            AllowedOrigins::Any => self.credentials,
+            AllowedOrigins::Exact(_) => false,
+            AllowedOrigins::List(_) => true,
+        }
+    }
+
+    // Headers added to the responses for GET and HEAD requests.
+    pub(crate) fn headers(&self, origin: Option<&[u8]>) -> Vec<Line> {
+        let mut headers = vec![];
+        if self.reflects_origin() {
+            headers.push(Line::with_array_ref_value(VARY, b"origin"));
+        }
+        if let Some(allow_origin) = self.allow_origin(origin) {
+            headers.push(Line::with_owned_value(CORS, allow_origin));
+            if self.credentials {
+                headers.push(Line::with_array_ref_value(CORS_ALLOW_CREDENTIALS, b"true"));
+            }
+        }
+        headers
+    }
+
+    // Headers for a preflight request, or None if the request shouldn't be allowed.
+    fn preflight_headers(&self, origin: &[u8], request_method: &[u8]) -> Option<Vec<Line>> {
+        if !matches!(request_method, method::GET | method::HEAD) {
+            return None;
+        }
+        self.allow_origin(Some(origin))?;
+        let mut headers = self.headers(Some(origin));
+        headers.push(Line::with_slice_value(CORS_ALLOW_METHODS, ALLOWED_METHODS));
+        if !self.allowed_headers.is_empty() {
+            headers.push(Line::with_owned_value(
+                CORS_ALLOW_HEADERS,
+                self.allowed_headers.join(", ").into_bytes(),
+            ));
+        }
+        if let Some(max_age) = self.max_age {
+            headers.push(Line::with_owned_value(
+                CORS_MAX_AGE,
+                format!("{max_age}").into_bytes(),
+            ));
+        }
+        Some(headers)
+    }
+}
+
+// Cors policies by path prefix.
+#[derive(Default)]
+pub(crate) struct CorsPolicies(Vec<(String, CorsPolicy)>);
+
+impl CorsPolicies {
+    pub(crate) fn add(&mut self, prefix: String, policy: CorsPolicy) {
+        self.0.retain(|(it, _)| *it != prefix);
+        self.0.push((prefix, policy));
+        // the longest prefix takes precedence
+        self.0.sort_by_key(|(prefix, _)| Reverse(prefix.len()));
+    }
+
+    pub(crate) fn find(&self, path: &str) -> Option<&CorsPolicy> {
+        self.0
+            .iter()
+            .find(|(prefix, _)| {
+                prefix.is_empty()
+                    || path
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .map(|(_, policy)| policy)
+    }
+}
+
+// Headers added to the responses for GET and HEAD requests.
+pub(crate) fn cors_headers(policy: Option<&CorsPolicy>, origin: Option<&[u8]>) -> Vec<Line> {
+    policy
+        .map(|policy| policy.headers(origin))
+        .unwrap_or_default()
+}
+
+// Headers for the response to an OPTIONS request.
+// Preflight requests (with origin and access-control-request-method headers) that are
+// not allowed by the policy get a response without any cors header.
+pub(crate) fn options_headers(
+    policy: Option<&CorsPolicy>,
+    origin: Option<&[u8]>,
+    request_method: Option<&[u8]>,
+) -> Vec<Line> {
+    let mut headers = vec![Line::with_slice_value(ALLOW, ALLOWED_METHODS_WITH_OPTIONS)];
+    if let (Some(origin), Some(request_method)) = (origin, request_method) {
+        if let Some(policy) = policy {
+            match policy.preflight_headers(origin, request_method) {
+                Some(preflight_headers) => headers.extend(preflight_headers),
+                None if policy.reflects_origin() => {
+                    headers.push(Line::with_array_ref_value(VARY, b"origin"))
+                }
+                None => {}
+            }
+        }
+    } else {
+        headers.extend(cors_headers(policy, origin));
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header<'a>(headers: &'a [Line], key: &[u8]) -> Option<&'a [u8]> {
+        headers
+            .iter()
+            .find(|it| it.key == key)
+            .map(|it| it.value.as_ref())
+    }
+
+    #[test]
+    fn any_origin() {
+        let policy = CorsPolicy::new(AllowedOrigins::Any);
+        let headers = policy.headers(Some(b"https://a.com"));
+        assert_eq!(header(&headers, CORS), Some(b"*".as_slice()));
+        assert_eq!(header(&headers, VARY), None);
+        let policy = CorsPolicy::new(AllowedOrigins::Any).with_credentials();
+        let headers = policy.headers(Some(b"https://a.com"));
+        assert_eq!(header(&headers, CORS), Some(b"https://a.com".as_slice()));
+        assert_eq!(
+            header(&headers, CORS_ALLOW_CREDENTIALS),
+            Some(b"true".as_slice())
+        );
+        assert_eq!(header(&headers, VARY), Some(b"origin".as_slice()));
+    }
+
+    #[test]
+    fn origin_list() {
+        let policy = CorsPolicy::new(AllowedOrigins::List(vec![
+            "https://a.com".to_string(),
+            "https://b.com".to_string(),
+        ]));
+        let headers = policy.headers(Some(b"https://b.com"));
+        assert_eq!(header(&headers, CORS), Some(b"https://b.com".as_slice()));
+        assert_eq!(header(&headers, VARY), Some(b"origin".as_slice()));
+        let headers = policy.headers(Some(b"https://c.com"));
+        assert_eq!(header(&headers, CORS), None);
+        assert_eq!(header(&headers, VARY), Some(b"origin".as_slice()));
+    }
+
+    #[test]
+    fn prefixes() {
+        let mut policies = CorsPolicies::default();
+        policies.add("".to_string(), CorsPolicy::new(AllowedOrigins::Any));
+        policies.add(
+            "/fonts".to_string(),
+            CorsPolicy::new(AllowedOrigins::Exact("https://a.com".to_string())),
+        );
+        let headers = cors_headers(policies.find("/fonts/a.woff2"), None);
+        assert_eq!(header(&headers, CORS), Some(b"https://a.com".as_slice()));
+        let headers = cors_headers(policies.find("/fonts"), None);
+        assert_eq!(header(&headers, CORS), Some(b"https://a.com".as_slice()));
+        let headers = cors_headers(policies.find("/fonts2/a.woff2"), None);
+        assert_eq!(header(&headers, CORS), Some(b"*".as_slice()));
+    }
+
+    #[test]
+    fn preflight() {
+        let mut policies = CorsPolicies::default();
+        policies.add(
+            "/api".to_string(),
+            CorsPolicy::new(AllowedOrigins::Exact("https://a.com".to_string()))
+                .with_allowed_headers(["authorization", "x-requested-with"])
+                .with_max_age(600),
+        );
+        let headers = options_headers(
+            policies.find("/api/a.json"),
+            Some(b"https://a.com"),
+            Some(b"GET"),
+        );
+        assert_eq!(header(&headers, CORS), Some(b"https://a.com".as_slice()));
+        assert_eq!(
+            header(&headers, CORS_ALLOW_METHODS),
+            Some(b"GET, HEAD".as_slice())
+        );
+        assert_eq!(
+            header(&headers, CORS_ALLOW_HEADERS),
+            Some(b"authorization, x-requested-with".as_slice())
+        );
+        assert_eq!(header(&headers, CORS_MAX_AGE), Some(b"600".as_slice()));
+        let headers = options_headers(
+            policies.find("/api/a.json"),
+            Some(b"https://a.com"),
+            Some(b"POST"),
+        );
+        assert_eq!(header(&headers, CORS), None);
+        let headers = options_headers(
+            policies.find("/a.json"),
+            Some(b"https://a.com"),
+            Some(b"GET"),
+        );
+        assert_eq!(header(&headers, CORS), None);
+        assert_eq!(
+            header(&headers, ALLOW),
+            Some(b"GET, HEAD, OPTIONS".as_slice())
+        );
+    }
+}
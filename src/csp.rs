@@ -0,0 +1,281 @@
+use crate::base64;
+use crate::http::OwnedOrStatic;
+use crate::http::headers::{CONTENT_TYPE, CSP, Line};
+use sha2::{Digest, Sha256};
+
+const UNSAFE_INLINE: &str = "'unsafe-inline'";
+
+// Inline content of an html page that is subject to the content security policy.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Inline {
+    // csp sources ('sha256-...') for the inline <script> elements
+    scripts: Vec<String>,
+    // csp sources ('sha256-...') for the <style> elements
+    styles: Vec<String>,
+    // whether some elements have a style attribute
+    style_attributes: bool,
+    // whether some elements have an event handler attribute (onclick, ...)
+    event_handlers: bool,
+}
+
+// Replaces 'unsafe-inline' in the content security policy of an html entry with the hashes of
+// its inline scripts and styles.
+pub(crate) fn add_csp_hashes(headers: &mut [Line], html: &[u8]) {
+    let is_html = headers
+        .iter()
+        .any(|it| it.key == CONTENT_TYPE && it.value.as_ref().starts_with(b"text/html"));
+    if !is_html {
+        return;
+    }
+    if let Some(line) = headers.iter_mut().find(|it| it.key == CSP) {
+        let inline = inline(html);
+        let policy = String::from_utf8_lossy(line.value.as_ref());
+        let policy = with_hashes(&policy, &inline);
+        line.value = OwnedOrStatic::Owned(policy.into_bytes());
+    }
+}
+
+// Policy with 'unsafe-inline' replaced with the hashes in the directives for inline elements.
+// script-src and style-src also apply to attributes, and are left as is when the page has
+// attributes that they apply to.
+fn with_hashes(policy: &str, inline: &Inline) -> String {
+    let directives = policy
+        .split(';')
+        .map(str::trim)
+        .filter(|it| !it.is_empty())
+        .collect::<Vec<_>>();
+    let has = |name: &str| {
+        directives
+            .iter()
+            .any(|it| directive_name(it).eq_ignore_ascii_case(name))
+    };
+    directives
+        .iter()
+        .map(|&directive| {
+            let name = directive_name(directive).to_ascii_lowercase();
+            let hashes = match name.as_str() {
+                "script-src-elem" => &inline.scripts,
+                "script-src" if !inline.event_handlers || has("script-src-attr") => &inline.scripts,
+                "style-src-elem" => &inline.styles,
+                "style-src" if !inline.style_attributes || has("style-src-attr") => &inline.styles,
+                _ => return directive.to_string(),
+            };
+            let sources = directive.split_ascii_whitespace().skip(1);
+            if !sources
+                .clone()
+                .any(|it| it.eq_ignore_ascii_case(UNSAFE_INLINE))
+            {
+                return directive.to_string();
+            }
+            let sources = sources
+                .filter(|it| !it.eq_ignore_ascii_case(UNSAFE_INLINE))
+                .chain(hashes.iter().map(String::as_str))
+                .collect::<Vec<_>>();
+            if sources.is_empty() {
+                format!("{name} 'none'")
+            } else {
+                format!("{name} {}", sources.join(" "))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn directive_name(directive: &str) -> &str {
+    directive
+        .split_ascii_whitespace()
+        .next()
+        .unwrap_or_default()
+}
+
+// Scans the html for inline <script> and <style> elements, and for attributes that inline
+// scripts or styles.
+fn inline(html: &[u8]) -> Inline {
+    let mut inline = Inline::default();
+    let mut i = 0;
+    while let Some(start) = html[i..].iter().position(|&b| b == b'<') {
+        i += start + 1;
+        let rest = &html[i..];
+        if rest.starts_with(b"!--") {
+            i += find(rest, b"-->").map(|it| it + 3).unwrap_or(rest.len());
+            continue;
+        }
+        if !rest.first().is_some_and(u8::is_ascii_alphabetic) {
+            continue;
+        }
+        let name_len = rest
+            .iter()
+            .position(|b| !b.is_ascii_alphanumeric() && *b != b'-')
+            .unwrap_or(rest.len());
+        let name = rest[..name_len].to_ascii_lowercase();
+        i += name_len;
+        let (attributes, len) = attributes(&html[i..]);
+        i += len;
+        for attribute in &attributes {
+            if attribute == b"style" {
+                inline.style_attributes = true;
+            } else if attribute.starts_with(b"on") {
+                inline.event_handlers = true;
+            }
+        }
+        let (hashes, end_tag) = match name.as_slice() {
+            b"script" => (&mut inline.scripts, b"</script".as_slice()),
+            b"style" => (&mut inline.styles, b"</style".as_slice()),
+            _ => continue,
+        };
+        let content_len = find_ignore_case(&html[i..], end_tag).unwrap_or(html.len() - i);
+        if name == b"style" || !attributes.iter().any(|it| it == b"src") {
+            let hash = sha256_source(&html[i..i + content_len]);
+            if !hashes.contains(&hash) {
+                hashes.push(hash);
+            }
+        }
+        i += content_len;
+    }
+    inline
+}
+
+// Lowercase names of the attributes of a start tag, and the length up to the end of the tag.
+fn attributes(tag: &[u8]) -> (Vec<Vec<u8>>, usize) {
+    let mut names = vec![];
+    let mut i = 0;
+    while i < tag.len() {
+        match tag[i] {
+            b'>' => return (names, i + 1),
+            b if b.is_ascii_whitespace() || b == b'/' => i += 1,
+            b'=' => {
+                i += 1;
+                while tag.get(i).is_some_and(u8::is_ascii_whitespace) {
+                    i += 1;
+                }
+                match tag.get(i) {
+                    Some(&quote @ (b'"' | b'\'')) => {
+                        i += 1;
+                        i += tag[i..]
+                            .iter()
+                            .position(|&b| b == quote)
+                            .map(|it| it + 1)
+                            .unwrap_or(tag.len() - i);
+                    }
+                    _ => {
+                        while tag
+                            .get(i)
+                            .is_some_and(|&b| !b.is_ascii_whitespace() && b != b'>')
+                        {
+                            i += 1;
+                        }
+                    }
+                }
+            }
+            _ => {
+                let len = tag[i..]
+                    .iter()
+                    .position(|&b| b.is_ascii_whitespace() || matches!(b, b'=' | b'>' | b'/'))
+                    .unwrap_or(tag.len() - i);
+                names.push(tag[i..i + len].to_ascii_lowercase());
+                i += len;
+            }
+        }
+    }
+    (names, tag.len())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|it| it == needle)
+}
+
+fn find_ignore_case(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|it| it.eq_ignore_ascii_case(needle))
+}
+
+// 'sha256-<base64 digest>' source for the content.
+fn sha256_source(content: &[u8]) -> String {
+    format!("'sha256-{}'", base64::encode(&Sha256::digest(content)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HTML: &[u8] = b"<!doctype html>\
+        <html><head>\
+        <!-- <script>ignored()</script> -->\
+        <STYLE>body{color:red}</STYLE>\
+        <script type=\"module\">main()</script>\
+        <script src=\"/a.js\"></script>\
+        <script>main()</script>\
+        </head><body><p title='a>b'>text</p></body></html>";
+
+    #[test]
+    fn sha256() {
+        // echo -n "alert('Hello, world.');" | openssl sha256 -binary | openssl base64
+        assert_eq!(
+            sha256_source(b"alert('Hello, world.');"),
+            "'sha256-qznLcsROx4GACP2dm0UCKCzCG+HiZ1guq6ZZDob/Tng='"
+        );
+    }
+
+    #[test]
+    fn scan() {
+        let inline = inline(HTML);
+        assert_eq!(inline.scripts, vec![sha256_source(b"main()")]);
+        assert_eq!(inline.styles, vec![sha256_source(b"body{color:red}")]);
+        assert!(!inline.style_attributes);
+        assert!(!inline.event_handlers);
+        let inline = super::inline(b"<p style=\"color:red\" onclick=\"f()\">a</p>");
+        assert!(inline.scripts.is_empty());
+        assert!(inline.style_attributes);
+        assert!(inline.event_handlers);
+    }
+
+    #[test]
+    fn policy() {
+        let inline = Inline {
+            scripts: vec!["'sha256-a'".to_string()],
+            styles: vec!["'sha256-b'".to_string(), "'sha256-c'".to_string()],
+            style_attributes: false,
+            event_handlers: true,
+        };
+        assert_eq!(
+            with_hashes(
+                "default-src 'self';script-src 'unsafe-inline';\
+                script-src-elem 'self' 'unsafe-inline';style-src 'self' 'unsafe-inline'",
+                &inline
+            ),
+            "default-src 'self'; script-src 'unsafe-inline'; \
+            script-src-elem 'self' 'sha256-a'; style-src 'self' 'sha256-b' 'sha256-c'"
+        );
+        assert_eq!(
+            with_hashes("script-src-elem 'unsafe-inline'", &Inline::default()),
+            "script-src-elem 'none'"
+        );
+        assert_eq!(
+            with_hashes("script-src 'self'", &inline),
+            "script-src 'self'"
+        );
+    }
+
+    #[test]
+    fn headers() {
+        let mut headers = vec![
+            Line::with_slice_value(CONTENT_TYPE, b"text/html"),
+            Line::with_slice_value(CSP, b"script-src-elem 'self' 'unsafe-inline'"),
+        ];
+        add_csp_hashes(&mut headers, b"<script>main()</script>");
+        assert_eq!(
+            headers[1].value.as_ref(),
+            format!("script-src-elem 'self' {}", sha256_source(b"main()")).as_bytes()
+        );
+        let mut headers = vec![
+            Line::with_slice_value(CONTENT_TYPE, b"text/plain"),
+            Line::with_slice_value(CSP, b"script-src-elem 'self' 'unsafe-inline'"),
+        ];
+        add_csp_hashes(&mut headers, b"<script>main()</script>");
+        assert_eq!(
+            headers[1].value.as_ref(),
+            b"script-src-elem 'self' 'unsafe-inline'"
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use zip_structs::zip_central_directory::ZipCDEntry;
+
+const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+// Header id of the extended timestamp extra field.
+const EXTENDED_TIMESTAMP: u16 = 0x5455;
+
+// Modification time of a zip entry, in seconds since the unix epoch.
+// The extended timestamp extra field is used when present, otherwise the DOS date and time
+// (which have no timezone and are assumed to be UTC).
+pub(crate) fn zip_timestamp(entry: &ZipCDEntry) -> Option<u64> {
+    extended_timestamp(&entry.extra_field)
+        .or_else(|| dos_timestamp(entry.last_mod_date, entry.last_mod_time))
+}
+
+fn extended_timestamp(mut extra_field: &[u8]) -> Option<u64> {
+    while extra_field.len() >= 4 {
+        let id = u16::from_le_bytes([extra_field[0], extra_field[1]]);
+        let size = u16::from_le_bytes([extra_field[2], extra_field[3]]) as usize;
+        let data = extra_field.get(4..4 + size)?;
+        if id == EXTENDED_TIMESTAMP {
+            // the first byte is a set of flags, bit 0 is set when the modification time is present
+            return match data {
+                [flags, a, b, c, d, ..] if flags & 1 == 1 => {
+                    u64::try_from(i32::from_le_bytes([*a, *b, *c, *d])).ok()
+                }
+                _ => None,
+            };
+        }
+        extra_field = &extra_field[4 + size..];
+    }
+    None
+}
+
+fn dos_timestamp(date: u16, time: u16) -> Option<u64> {
+    let year = 1980 + (date >> 9) as u64;
+    let month = ((date >> 5) & 0x0f) as u64;
+    let day = (date & 0x1f) as u64;
+    let hours = (time >> 11) as u64;
+    let minutes = ((time >> 5) & 0x3f) as u64;
+    let seconds = ((time & 0x1f) * 2) as u64;
+    timestamp(year, month, day, hours, minutes, seconds)
+}
+
+fn timestamp(
+    year: u64,
+    month: u64,
+    day: u64,
+    hours: u64,
+    minutes: u64,
+    seconds: u64,
+) -> Option<u64> {
+    if year < 1970
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hours > 23
+        || minutes > 59
+        || seconds > 60
+    {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) * 86400 + hours * 3600 + minutes * 60 + seconds)
+}
+
+// Formats a timestamp as an IMF-fixdate (Sun, 06 Nov 1994 08:49:37 GMT).
+pub(crate) fn format_http_date(timestamp: u64) -> String {
+    let days = timestamp / 86400;
+    let seconds = timestamp % 86400;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{}, {day:02} {} {year} {:02}:{:02}:{:02} GMT",
+        DAYS[((days + 4) % 7) as usize],
+        MONTHS[month as usize - 1],
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+// Parses an http date, in any of the three formats that recipients are required to accept:
+// IMF-fixdate (Sun, 06 Nov 1994 08:49:37 GMT), RFC 850 (Sunday, 06-Nov-94 08:49:37 GMT)
+// and asctime (Sun Nov  6 08:49:37 1994).
+pub(crate) fn parse_http_date(value: &[u8]) -> Option<u64> {
+    let value = std::str::from_utf8(value).ok()?;
+    let tokens = value
+        .split([' ', ',', '-', ':'])
+        .filter(|it| !it.is_empty())
+        .collect::<Vec<_>>();
+    let (day, month, year, time) = match tokens[..] {
+        [_, day, month, year, hours, minutes, seconds, "GMT"] => {
+            (day, month, year, [hours, minutes, seconds])
+        }
+        [_, month, day, hours, minutes, seconds, year] => {
+            (day, month, year, [hours, minutes, seconds])
+        }
+        _ => return None,
+    };
+    let month = MONTHS.iter().position(|&it| it == month)? as u64 + 1;
+    let year = match number(year)? {
+        // two digit years from RFC 850 dates
+        year @ 0..70 => year + 2000,
+        year @ 70..100 => year + 1900,
+        year => year,
+    };
+    let [hours, minutes, seconds] = time;
+    timestamp(
+        year,
+        month,
+        number(day)?,
+        number(hours)?,
+        number(minutes)?,
+        number(seconds)?,
+    )
+}
+
+fn number(value: &str) -> Option<u64> {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+// Days since the unix epoch (proleptic gregorian calendar), for dates after the epoch.
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMESTAMP: u64 = 784111777;
+
+    #[test]
+    fn format() {
+        assert_eq!(format_http_date(TIMESTAMP), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(format_http_date(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(format_http_date(951782400), "Tue, 29 Feb 2000 00:00:00 GMT");
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_http_date(b"Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(TIMESTAMP)
+        );
+        assert_eq!(
+            parse_http_date(b"Sunday, 06-Nov-94 08:49:37 GMT"),
+            Some(TIMESTAMP)
+        );
+        assert_eq!(
+            parse_http_date(b"Sun Nov  6 08:49:37 1994"),
+            Some(TIMESTAMP)
+        );
+        assert_eq!(
+            parse_http_date(b"Tue, 29 Feb 2000 00:00:00 GMT"),
+            Some(951782400)
+        );
+        assert_eq!(parse_http_date(b"Sun, 06 Nov 1994 08:49:37 PST"), None);
+        assert_eq!(parse_http_date(b"Sun, 06 Foo 1994 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date(b"Sun, 06 Nov 1994 25:49:37 GMT"), None);
+        assert_eq!(parse_http_date(b"784111777"), None);
+    }
+
+    #[test]
+    fn dos() {
+        // 1994-11-06 08:49:36
+        let date = (14 << 9) | (11 << 5) | 6;
+        let time = (8 << 11) | (49 << 5) | 18;
+        assert_eq!(dos_timestamp(date, time), Some(TIMESTAMP - 1));
+        assert_eq!(dos_timestamp(0, 0), None);
+    }
+
+    #[test]
+    fn extended() {
+        let mut extra_field = vec![0x01, 0x00, 0x02, 0x00, 0xff, 0xff];
+        extra_field.extend_from_slice(&[0x55, 0x54, 0x05, 0x00, 0x01]);
+        extra_field.extend_from_slice(&(TIMESTAMP as i32).to_le_bytes());
+        assert_eq!(extended_timestamp(&extra_field), Some(TIMESTAMP));
+        assert_eq!(
+            extended_timestamp(&[0x55, 0x54, 0x05, 0x00, 0x00, 0x01, 0x02, 0x03, 0x04]),
+            None
+        );
+        assert_eq!(extended_timestamp(&[0x55, 0x54, 0x05]), None);
+    }
+}
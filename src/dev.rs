@@ -0,0 +1,294 @@
+use crate::builder::{Builder, CustomHeaderSelector, PathPrefix, ZipPrefix};
+use crate::errors::Result;
+use crate::handler::{Entry, Handler, HeaderSelector, HeadersAndCompression, PendingCompression};
+use crate::http::OwnedOrStatic;
+use crate::http::headers::{CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE, ETAG, Line};
+use crate::source::{Directory, Source};
+use crate::swappable::SwappableHandler;
+use bytes::Bytes;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+// Routes of the live reload script and of its event stream, under the path prefix.
+const SCRIPT: &str = "/__live_reload.js";
+const EVENTS: &str = "/__live_reload";
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+// Delay before the browser reconnects to the event stream, in milliseconds.
+const RETRY: u64 = 500;
+
+/// Serves the files of a directory with a handler that is rebuilt when they change.
+/// The handler is built in development mode: the content isn't compressed, responses have a
+/// `no-cache` cache-control header, and html pages get a script that reloads them after each
+/// rebuild.
+/// The directory is polled, and any change rebuilds the whole handler from all the files, which
+/// is meant for sites under development rather than for large directories.
+/// The directory is watched until the returned handler is dropped.
+pub fn watch(directory: impl AsRef<Path>) -> Result<Arc<SwappableHandler>> {
+    watch_with(directory, |builder| builder)
+}
+
+/// Same as `watch`, with a function that configures the builder (prefixes, header selector,
+/// error pages, ...) before each build.
+pub fn watch_with<Z, R, H, F>(
+    directory: impl AsRef<Path>,
+    configure: F,
+) -> Result<Arc<SwappableHandler>>
+where
+    Z: ZipPrefix,
+    R: PathPrefix,
+    H: CustomHeaderSelector<'static>,
+    F: Fn(
+            Builder<'static, 'static, (), (), (), (), Directory>,
+        ) -> Builder<'static, 'static, Z, R, H, (), Directory>
+        + Send
+        + 'static,
+{
+    let (handler, mut watcher) = Watcher::new(directory.as_ref(), configure)?;
+    let weak = Arc::downgrade(&handler);
+    thread::Builder::new()
+        .name("zip_static_handler_watcher".to_string())
+        .spawn(move || {
+            loop {
+                thread::sleep(POLL_INTERVAL);
+                let Some(handler) = weak.upgrade() else {
+                    break;
+                };
+                watcher.poll(&handler);
+            }
+        })?;
+    Ok(handler)
+}
+
+// Directory being watched, with the snapshot of its files and the version of the handler.
+struct Watcher<F> {
+    directory: PathBuf,
+    configure: F,
+    version: u64,
+    files: Vec<(String, Option<SystemTime>, u64)>,
+}
+
+impl<Z, R, H, F> Watcher<F>
+where
+    Z: ZipPrefix,
+    R: PathPrefix,
+    H: CustomHeaderSelector<'static>,
+    F: Fn(
+        Builder<'static, 'static, (), (), (), (), Directory>,
+    ) -> Builder<'static, 'static, Z, R, H, (), Directory>,
+{
+    // Builds the handler for the current files.
+    fn new(directory: &Path, configure: F) -> Result<(Arc<SwappableHandler>, Self)> {
+        let directory = directory.to_path_buf();
+        // the version changes when the server restarts, so that open pages are reloaded as well
+        let version = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|it| it.as_millis() as u64)
+            .unwrap_or_default();
+        let files = snapshot(&directory);
+        let handler = Arc::new(SwappableHandler::new(
+            configure(dev_builder(&directory, version)).try_build()?,
+        ));
+        let watcher = Self {
+            directory,
+            configure,
+            version,
+            files,
+        };
+        Ok((handler, watcher))
+    }
+
+    // Rebuilds the handler if the files changed since the last poll, and returns whether they did.
+    fn poll(&mut self, handler: &SwappableHandler) -> bool {
+        let current = snapshot(&self.directory);
+        if current == self.files {
+            return false;
+        }
+        self.files = current;
+        self.version += 1;
+        match handler.replace((self.configure)(dev_builder(&self.directory, self.version))) {
+            Ok(()) => info!(version = self.version, "handler rebuilt"),
+            Err(err) => warn!("failed to rebuild handler: {err}"),
+        }
+        true
+    }
+}
+
+fn dev_builder(
+    directory: &Path,
+    version: u64,
+) -> Builder<'static, 'static, (), (), (), (), Directory> {
+    Handler::builder()
+        .with_directory(directory)
+        .with_dev_mode(version)
+}
+
+// Names, modification times and sizes of the files under the directory.
+fn snapshot(directory: &Path) -> Vec<(String, Option<SystemTime>, u64)> {
+    let source = Directory::new(directory);
+    let Ok(files) = source.files() else {
+        return vec![];
+    };
+    files
+        .names()
+        .iter()
+        .filter_map(|name| {
+            let metadata = fs::metadata(directory.join(name)).ok()?;
+            Some((name.clone(), metadata.modified().ok(), metadata.len()))
+        })
+        .collect()
+}
+
+// Changes an entry for development: the pending compressions are skipped, the cache-control
+// header is replaced with no-cache, and html pages get the live reload script.
+pub(crate) fn dev_entry(entry: &mut Entry, pending: Vec<PendingCompression>, path_prefix: &str) {
+    for it in pending.into_iter().rev() {
+        entry.encoded.remove(it.position);
+    }
+    let mut is_html = false;
+    for line in entry.headers.iter_mut() {
+        if line.key == CACHE_CONTROL {
+            line.value = OwnedOrStatic::Static(b"no-cache");
+        } else if line.key == CONTENT_TYPE {
+            is_html = line.value.as_ref().starts_with(b"text/html");
+        }
+    }
+    let Some(content) = entry.content.as_ref().filter(|_| is_html) else {
+        return;
+    };
+    let content = with_script(content, path_prefix);
+    // the pre-compressed representations don't have the script
+    entry.encoded.clear();
+    for line in entry
+        .headers
+        .iter_mut()
+        .filter(|it| it.key == CONTENT_LENGTH)
+    {
+        line.value = OwnedOrStatic::Owned(content.len().to_string().into_bytes());
+    }
+    entry.content = Some(content);
+}
+
+// Inserts the script element before the end of the body, or at the end of the page.
+fn with_script(html: &[u8], path_prefix: &str) -> Bytes {
+    let script = format!("<script src=\"{path_prefix}{SCRIPT}\"></script>");
+    let position = html
+        .windows(7)
+        .rposition(|it| it.eq_ignore_ascii_case(b"</body>"))
+        .unwrap_or(html.len());
+    let mut content = Vec::with_capacity(html.len() + script.len());
+    content.extend_from_slice(&html[..position]);
+    content.extend_from_slice(script.as_bytes());
+    content.extend_from_slice(&html[position..]);
+    Bytes::from(content)
+}
+
+// Entries for the live reload script and its event stream.
+// The event stream sends the version of the handler and is closed, and the browser reconnects
+// after the retry delay. The script reloads the page when the version changes.
+pub(crate) fn dev_routes(
+    header_selector: &dyn HeaderSelector,
+    path_prefix: &str,
+    version: u64,
+) -> Vec<(String, Entry)> {
+    let mut routes = vec![];
+    if let Some(HeadersAndCompression { mut headers, .. }) =
+        header_selector.headers_for_extension(&SCRIPT[1..], "js")
+    {
+        let script = format!(
+            "let version;\
+            new EventSource(\"{path_prefix}{EVENTS}\").onmessage = (event) => {{\
+            if (version === undefined) version = event.data;\
+            else if (event.data !== version) location.reload();\
+            }};"
+        );
+        headers.retain(|it| it.key != CACHE_CONTROL);
+        headers.push(Line::with_slice_value(CACHE_CONTROL, b"no-cache"));
+        routes.push((format!("{path_prefix}{SCRIPT}"), entry(headers, script)));
+    }
+    let headers = vec![
+        Line::with_slice_value(CONTENT_TYPE, b"text/event-stream"),
+        Line::with_slice_value(CACHE_CONTROL, b"no-store"),
+    ];
+    let events = format!("retry: {RETRY}\ndata: {version}\n\n");
+    routes.push((format!("{path_prefix}{EVENTS}"), entry(headers, events)));
+    routes
+}
+
+fn entry(mut headers: Vec<Line>, content: String) -> Entry {
+    let etag = format!("\"{:x}\"", crc32fast::hash(content.as_bytes()));
+    headers.push(Line::with_owned_value(ETAG, etag.as_bytes().to_vec()));
+    headers.push(Line::with_owned_value(
+        CONTENT_LENGTH,
+        content.len().to_string().into_bytes(),
+    ));
+    Entry {
+        headers,
+        content: Some(Bytes::from(content)),
+        encoded: vec![],
+        etag: Some(etag),
+        last_modified: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::tests::{TestRequest, TestResponse};
+    use crate::http::headers::{ACCEPT_ENCODING, CONTENT_ENCODING};
+    use test_tracing::test;
+
+    fn temp_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("zip_static_handler_{name}_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn events(handler: &SwappableHandler) -> TestResponse {
+        handler.handle(TestRequest::get(EVENTS))
+    }
+
+    #[test]
+    fn dev_mode() {
+        let directory = temp_directory("dev_mode");
+        fs::write(directory.join("index.html"), "<html><body>a</BODY></html>").unwrap();
+        fs::write(directory.join("style.css"), "p{}").unwrap();
+        let (handler, mut watcher) = Watcher::new(&directory, |builder| builder).unwrap();
+        let response = handler.handle(TestRequest::get("/").with_header(ACCEPT_ENCODING, b"br"));
+        let expected = format!("<html><body>a<script src=\"{SCRIPT}\"></script></BODY></html>");
+        assert_eq!(response.body.as_deref(), Some(expected.as_bytes()));
+        assert_eq!(
+            response.header(CONTENT_LENGTH),
+            Some(expected.len().to_string().as_bytes())
+        );
+        assert_eq!(response.header(CACHE_CONTROL), Some(b"no-cache".as_slice()));
+        let response =
+            handler.handle(TestRequest::get("/style.css").with_header(ACCEPT_ENCODING, b"br"));
+        assert_eq!(response.header(CONTENT_ENCODING), None);
+        assert_eq!(response.header(CACHE_CONTROL), Some(b"no-cache".as_slice()));
+        assert_eq!(handler.handle(TestRequest::get(SCRIPT)).status, 200);
+        let response = events(&handler);
+        assert_eq!(
+            response.header(CONTENT_TYPE),
+            Some(b"text/event-stream".as_slice())
+        );
+        let version = response.body.unwrap();
+        assert!(!watcher.poll(&handler));
+        fs::write(directory.join("style.css"), "p{margin:0}").unwrap();
+        let rebuilt = watcher.poll(&handler);
+        fs::remove_dir_all(&directory).unwrap();
+        assert!(rebuilt);
+        assert_ne!(events(&handler).body.unwrap(), version);
+        assert_eq!(
+            handler
+                .handle(TestRequest::get("/style.css"))
+                .body
+                .as_deref(),
+            Some(b"p{margin:0}".as_slice())
+        );
+    }
+}
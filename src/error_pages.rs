@@ -0,0 +1,135 @@
+use crate::handler::{EncodedContent, Entry};
+use crate::http::headers::{
+    ACCEPT_RANGES, ALLOW, CACHE_CONTROL, CONTENT_LENGTH, ETAG, LAST_MODIFIED, Line,
+};
+
+// Status codes that an error page applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ErrorStatus {
+    Code(u16),
+    // first digit of the status code (4 for 4xx)
+    Class(u16),
+}
+
+impl ErrorStatus {
+    // Parses "404" or "4xx".
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value.as_bytes() {
+            [class @ b'4'..=b'5', b'x' | b'X', b'x' | b'X'] => {
+                Some(Self::Class((class - b'0') as u16))
+            }
+            [b'4'..=b'5', b'0'..=b'9', b'0'..=b'9'] => value.parse().ok().map(Self::Code),
+            _ => None,
+        }
+    }
+
+    fn matches(self, status: u16) -> bool {
+        match self {
+            Self::Code(code) => code == status,
+            Self::Class(class) => status / 100 == class,
+        }
+    }
+}
+
+struct ErrorPage {
+    // directory path, with a trailing slash (/docs/)
+    directory: String,
+    status: ErrorStatus,
+    route: String,
+}
+
+// Error pages by directory.
+#[derive(Default)]
+pub(crate) struct ErrorPages(Vec<ErrorPage>);
+
+impl ErrorPages {
+    pub(crate) fn add(&mut self, directory: String, status: ErrorStatus, route: String) {
+        self.0.push(ErrorPage {
+            directory,
+            status,
+            route,
+        });
+    }
+
+    // Route of the error page for the request path, from the closest directory.
+    // In the same directory, a page for the exact status code is preferred over a page for
+    // the status class. Pages whose route isn't allowed are skipped for the ones in the parent
+    // directories.
+    pub(crate) fn find(
+        &self,
+        path: &str,
+        status: u16,
+        allowed: impl Fn(&str) -> bool,
+    ) -> Option<&str> {
+        self.0
+            .iter()
+            .filter(|it| {
+                it.status.matches(status)
+                    && (path.starts_with(it.directory.as_str())
+                        || path == &it.directory[..it.directory.len() - 1])
+                    && allowed(&it.route)
+            })
+            .max_by_key(|it| {
+                (
+                    it.directory.len(),
+                    matches!(it.status, ErrorStatus::Code(_)),
+                )
+            })
+            .map(|it| it.route.as_str())
+    }
+}
+
+// Headers of an error response with an error page body.
+// The validators and cache headers of the page don't apply to the error response.
+pub(crate) fn error_page_headers<'a>(
+    error_headers: &'a [Line],
+    page: &'a Entry,
+    encoded: Option<&'a EncodedContent>,
+) -> impl Iterator<Item = &'a Line> {
+    error_headers
+        .iter()
+        .filter(|line| line.key != CONTENT_LENGTH)
+        .chain(page.headers(encoded).filter(|line| {
+            !matches!(
+                line.key,
+                ALLOW | ACCEPT_RANGES | CACHE_CONTROL | ETAG | LAST_MODIFIED
+            )
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(ErrorStatus::parse("404"), Some(ErrorStatus::Code(404)));
+        assert_eq!(ErrorStatus::parse("4xx"), Some(ErrorStatus::Class(4)));
+        assert_eq!(ErrorStatus::parse("5XX"), Some(ErrorStatus::Class(5)));
+        assert_eq!(ErrorStatus::parse("200"), None);
+        assert_eq!(ErrorStatus::parse("40"), None);
+        assert_eq!(ErrorStatus::parse("6xx"), None);
+    }
+
+    #[test]
+    fn find() {
+        let mut pages = ErrorPages::default();
+        pages.add("/".to_string(), ErrorStatus::Code(404), "/404".to_string());
+        pages.add("/".to_string(), ErrorStatus::Class(4), "/4xx".to_string());
+        pages.add(
+            "/docs/".to_string(),
+            ErrorStatus::Class(4),
+            "/docs/4xx".to_string(),
+        );
+        let any = |_: &str| true;
+        assert_eq!(pages.find("/a", 404, any), Some("/404"));
+        assert_eq!(pages.find("/a", 405, any), Some("/4xx"));
+        assert_eq!(pages.find("/a", 500, any), None);
+        assert_eq!(pages.find("/docs/a", 404, any), Some("/docs/4xx"));
+        assert_eq!(pages.find("/docs", 404, any), Some("/docs/4xx"));
+        assert_eq!(pages.find("/docs2", 404, any), Some("/404"));
+        let public = |route: &str| !route.starts_with("/docs/");
+        assert_eq!(pages.find("/docs/a", 404, public), Some("/404"));
+        assert_eq!(pages.find("/docs/a", 500, public), None);
+    }
+}
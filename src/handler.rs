@@ -1,98 +1,396 @@
-use crate::compression::{brotli_decompressed_crc32, compress_brotli, decompress_entry};
+#[cfg(feature = "auth")]
+use crate::auth::AuthPolicies;
+use crate::builder::{CompressionSelector, HiddenPaths, UrlStyle};
+#[cfg(feature = "zstd")]
+use crate::compression::zstd_decompressed_crc32;
+use crate::compression::{Compression, brotli_decompressed_crc32, gzip_from_deflate};
+use crate::conditional::Preconditions;
+use crate::cors::{CorsPolicies, cors_headers, options_headers};
+use crate::date::format_http_date;
+use crate::error_pages::{ErrorPages, error_page_headers};
 use crate::errors::Result;
 use crate::http::headers::{
-    CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, ETAG, IF_MATCH, IF_NONE_MATCH, LOCATION, Line,
+    ACCEPT, ACCEPT_ENCODING, ACCEPT_RANGES, AUTHORIZATION, CACHE_CONTROL, CONTENT_ENCODING,
+    CONTENT_LENGTH, CORS_REQUEST_METHOD, ETAG, IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+    IF_RANGE, IF_UNMODIFIED_SINCE, LAST_MODIFIED, LOCATION, Line, ORIGIN, RANGE, VARY,
 };
 use crate::http::method;
 use crate::http::request::Request;
 use crate::http::response::StatusCode;
-use crate::path::{extension, filename, path};
+use crate::negotiation::preferred_encoding;
+use crate::normalize::normalize_path;
+use crate::path::{entry_route, extension, filename, is_hidden, path, relative_name};
+use crate::range::{RangeResponse, range_response};
+use crate::redirects::{RedirectTarget, Redirects};
+#[cfg(feature = "auth")]
+use crate::signed_urls::SignedUrls;
+use crate::source::Files;
+use crate::spa::{SpaFallback, SpaFallbacks};
 use bytes::Bytes;
 use std::collections::HashMap;
-use std::io::Cursor;
+#[cfg(feature = "auth")]
+use std::time::SystemTime;
 use tracing::{debug, trace};
-use zip_structs::zip_central_directory::ZipCDEntry;
-use zip_structs::zip_local_file_header::ZipLocalFileHeader;
+
+const ACME_CHALLENGE: &str = ".well-known/acme-challenge/";
 
 pub struct Handler {
     pub(crate) paths: HashMap<String, Entry>,
     pub(crate) error_headers: &'static [Line],
+    pub(crate) cors: CorsPolicies,
+    #[cfg(feature = "auth")]
+    pub(crate) auth: AuthPolicies,
+    #[cfg(feature = "auth")]
+    pub(crate) signed_urls: SignedUrls,
+    pub(crate) error_pages: ErrorPages,
+    pub(crate) spa: SpaFallbacks,
+    pub(crate) canonical_redirects: bool,
+    pub(crate) redirects: Redirects,
+    // index of the layer that the entry of a route comes from, for layered sources
+    pub(crate) layers: HashMap<String, usize>,
+}
+
+// Response for a path without entry.
+pub(crate) enum Fallback<'a> {
+    // rewrite from the _redirects file, or index page of a single page application, with the
+    // route of the entry
+    Entry(&'a str, &'a Entry, Option<&'a SpaFallback>),
+    // redirection from the _redirects file
+    Redirect(StatusCode, String),
 }
 
 impl Handler {
     pub fn entry(&self, path: &str) -> Option<&Entry> {
         self.paths.get(path)
     }
+    /// Index of the layer that the entry at the path comes from, for a handler built with
+    /// `Builder::with_layers`.
+    pub fn layer(&self, path: &str) -> Option<usize> {
+        self.layers.get(path).copied()
+    }
     pub fn handle<Resp, Req: Request<Resp>>(&self, request: Req) -> Resp {
         if let Some(value) = request.first_header_value(CONTENT_LENGTH)
             && value != b"0"
         {
             return request.response(StatusCode::BadRequest, self.error_headers.iter(), None);
         }
+        let Some(normalized) = normalize_path(request.path()) else {
+            return request.response(StatusCode::BadRequest, self.error_headers.iter(), None);
+        };
+        if self.canonical_redirects
+            && let Some(mut location) = normalized.canonical
+        {
+            // the query isn't part of the request path with most frameworks
+            let query = request.query();
+            if !query.is_empty() && !location.contains('?') {
+                location.push('?');
+                location.push_str(&String::from_utf8_lossy(query));
+            }
+            return request.response(
+                StatusCode::PermanentRedirect,
+                self.redirect_headers(location).iter(),
+                None,
+            );
+        }
+        // preflight requests are sent without credentials
+        let is_preflight = request.method() == method::OPTIONS
+            && request.first_header_value(CORS_REQUEST_METHOD).is_some();
+        if !is_preflight
+            && let Some(challenges) = self.challenges(
+                normalized.path.as_ref(),
+                request.first_header_value(AUTHORIZATION),
+            )
+        {
+            return self.handle_error(request, StatusCode::Unauthorized, &challenges);
+        }
+        if !self.is_signature_valid(normalized.path.as_ref(), request.query()) {
+            return self.handle_error(request, StatusCode::Forbidden, &[]);
+        }
         let is_get = match request.method() {
             method::GET => true,
             method::HEAD => false,
-            _ => {
-                return request.response(
-                    StatusCode::MethodNotAllowed,
-                    self.error_headers.iter(),
-                    None,
-                );
-            }
+            method::OPTIONS => return self.handle_options(request),
+            _ => return self.handle_error(request, StatusCode::MethodNotAllowed, &[]),
+        };
+        let path = normalized.path;
+        let resolved = match self.entry(path.as_ref()) {
+            Some(entry) => Some((entry, None)),
+            None => match self.fallback(path.as_ref(), request.first_header_value(ACCEPT)) {
+                Some(Fallback::Entry(target, entry, fallback)) => {
+                    // the target can be under a protected or signed prefix that the request path
+                    // isn't
+                    if let Some(challenges) =
+                        self.challenges(target, request.first_header_value(AUTHORIZATION))
+                    {
+                        return self.handle_error(request, StatusCode::Unauthorized, &challenges);
+                    }
+                    if !self.is_signature_valid(target, request.query()) {
+                        return self.handle_error(request, StatusCode::Forbidden, &[]);
+                    }
+                    Some((entry, fallback))
+                }
+                Some(Fallback::Redirect(status, location)) => {
+                    return request.response(status, self.redirect_headers(location).iter(), None);
+                }
+                None => None,
+            },
         };
-        let path = String::from_utf8_lossy(request.path());
-        if let Some(file) = self.entry(path.as_ref()) {
+        if let Some((file, fallback)) = resolved {
             let headers = &file.headers;
+            let mut extra_headers = cors_headers(
+                self.cors.find(path.as_ref()),
+                request.first_header_value(ORIGIN),
+            );
+            extra_headers.extend(fallback.and_then(|it| it.headers()));
             if file.etag.is_some() {
-                let etag = file.etag.as_ref().map(|it| it.as_bytes());
-                let none_match = request.first_header_value(IF_NONE_MATCH);
-                let if_match = request.first_header_value(IF_MATCH);
-                if none_match.is_some() && none_match == etag {
+                let preconditions = Preconditions {
+                    if_match: request.first_header_value(IF_MATCH),
+                    if_none_match: request.first_header_value(IF_NONE_MATCH),
+                    if_modified_since: request.first_header_value(IF_MODIFIED_SINCE),
+                    if_unmodified_since: request.first_header_value(IF_UNMODIFIED_SINCE),
+                };
+                if let Some(status) = preconditions.evaluate(file) {
                     request.response(
-                        StatusCode::NotModified,
+                        status,
                         headers
                             .iter()
-                            .filter(|&line| !matches!(line.key, CONTENT_LENGTH | CONTENT_ENCODING)),
+                            .filter(|&line| !matches!(line.key, CONTENT_LENGTH | CONTENT_ENCODING))
+                            .chain(extra_headers.iter()),
                         None,
                     )
-                } else if if_match.is_some() && if_match != etag {
-                    request.response(
-                        StatusCode::PreconditionFailed,
-                        headers
-                            .iter()
-                            .filter(|&line| !matches!(line.key, CONTENT_LENGTH | CONTENT_ENCODING)),
-                        None,
+                } else if is_get
+                    && let Some(range) = range_response(
+                        file,
+                        request.first_header_value(RANGE),
+                        request.first_header_value(IF_RANGE),
                     )
-                } else if let Some(ref body) = file.content {
+                {
+                    match range {
+                        RangeResponse::Partial { headers, body } => request.response(
+                            StatusCode::PartialContent,
+                            headers.iter().chain(extra_headers.iter()),
+                            Some(body),
+                        ),
+                        RangeResponse::NotSatisfiable { headers } => request.response(
+                            StatusCode::RangeNotSatisfiable,
+                            headers.iter().chain(extra_headers.iter()),
+                            None,
+                        ),
+                    }
+                } else if file.content.is_some() {
+                    let encoded = file.encoded(request.first_header_value(ACCEPT_ENCODING));
                     request.response(
                         StatusCode::OK,
-                        headers.iter(),
-                        if is_get { Some(body.clone()) } else { None },
+                        file.headers(encoded).chain(extra_headers.iter()),
+                        if is_get { file.body(encoded) } else { None },
                     )
                 } else if headers.iter().any(|it| it.key == LOCATION) {
-                    request.response(StatusCode::TemporaryRedirect, headers.iter(), None)
+                    request.response(
+                        StatusCode::TemporaryRedirect,
+                        headers.iter().chain(extra_headers.iter()),
+                        None,
+                    )
                 } else {
                     request.response(
                         StatusCode::NoContent,
                         headers
                             .iter()
-                            .filter(|&line| !matches!(line.key, CONTENT_LENGTH | CONTENT_ENCODING)),
+                            .filter(|&line| !matches!(line.key, CONTENT_LENGTH | CONTENT_ENCODING))
+                            .chain(extra_headers.iter()),
                         None,
                     )
                 }
             } else {
-                request.response(StatusCode::PermanentRedirect, headers.iter(), None)
+                request.response(
+                    StatusCode::PermanentRedirect,
+                    headers.iter().chain(extra_headers.iter()),
+                    None,
+                )
             }
         } else {
-            request.response(StatusCode::NotFound, self.error_headers.iter(), None)
+            self.handle_error(request, StatusCode::NotFound, &[])
         }
     }
+    // Rule from the _redirects file, or index page of a single page application, for a path
+    // without entry.
+    pub(crate) fn fallback(&self, path: &str, accept: Option<&[u8]>) -> Option<Fallback<'_>> {
+        match self.redirects.find(path) {
+            Some(RedirectTarget::Redirect { status, location }) => {
+                return Some(Fallback::Redirect(status, location));
+            }
+            Some(RedirectTarget::Rewrite(target)) => {
+                return self
+                    .paths
+                    .get_key_value(&target)
+                    .map(|(route, entry)| Fallback::Entry(route, entry, None));
+            }
+            None => {}
+        }
+        let fallback = self.spa.find(path, accept)?;
+        self.paths
+            .get_key_value(fallback.index())
+            .map(|(route, entry)| Fallback::Entry(route, entry, Some(fallback)))
+    }
+    fn handle_options<Resp, Req: Request<Resp>>(&self, request: Req) -> Resp {
+        let path = normalize_path(request.path())
+            .map(|it| it.path)
+            .unwrap_or_default();
+        // a preflight doesn't carry the Accept header of the actual request, so html is assumed to
+        // find the paths served by a single page app fallback
+        let found = self.entry(path.as_ref()).is_some()
+            || matches!(
+                self.fallback(path.as_ref(), Some(b"text/html")),
+                Some(Fallback::Entry(..))
+            );
+        if found {
+            let headers = options_headers(
+                self.cors.find(path.as_ref()),
+                request.first_header_value(ORIGIN),
+                request.first_header_value(CORS_REQUEST_METHOD),
+            );
+            request.response(StatusCode::NoContent, headers.iter(), None)
+        } else {
+            self.handle_error(request, StatusCode::NotFound, &[])
+        }
+    }
+    fn handle_error<Resp, Req: Request<Resp>>(
+        &self,
+        request: Req,
+        status: StatusCode,
+        extra_headers: &[Line],
+    ) -> Resp {
+        let page = self.error_page(request.path(), status);
+        if let Some(page) = page {
+            let encoded = page.encoded(request.first_header_value(ACCEPT_ENCODING));
+            let body = if request.method() == method::HEAD {
+                None
+            } else {
+                page.body(encoded)
+            };
+            request.response(
+                status,
+                error_page_headers(self.error_headers, page, encoded).chain(extra_headers.iter()),
+                body,
+            )
+        } else {
+            request.response(
+                status,
+                self.error_headers.iter().chain(extra_headers.iter()),
+                None,
+            )
+        }
+    }
+    /// Headers of the redirection to the canonical form of a request path.
+    pub(crate) fn redirect_headers(&self, location: String) -> Vec<Line> {
+        self.error_headers
+            .iter()
+            .cloned()
+            .chain([Line::with_owned_value(LOCATION, location.into_bytes())])
+            .collect()
+    }
+    /// Challenges (www-authenticate headers) of the 401 response, if the auth policy of the path
+    /// doesn't accept the credentials.
+    #[cfg_attr(not(feature = "auth"), allow(unused_variables))]
+    pub(crate) fn challenges(&self, path: &str, authorization: Option<&[u8]>) -> Option<Vec<Line>> {
+        #[cfg(feature = "auth")]
+        if let Some(policy) = self.auth.find(path)
+            && !policy.is_authorized(authorization)
+        {
+            return Some(policy.challenges());
+        }
+        None
+    }
+    /// Whether the query has a valid signature for the path, when it's under a signed prefix.
+    #[cfg_attr(not(feature = "auth"), allow(unused_variables))]
+    pub(crate) fn is_signature_valid(&self, path: &str, query: &[u8]) -> bool {
+        #[cfg(feature = "auth")]
+        if !self.signed_urls.is_allowed(path, query, SystemTime::now()) {
+            return false;
+        }
+        true
+    }
+    /// Whether the path requires credentials or a signature.
+    #[cfg_attr(not(feature = "auth"), allow(unused_variables))]
+    fn is_protected(&self, path: &str) -> bool {
+        #[cfg(feature = "auth")]
+        if self.auth.find(path).is_some() || self.signed_urls.is_signed(path) {
+            return true;
+        }
+        false
+    }
+    /// Error page for the given request path and status, from the closest directory.
+    pub(crate) fn error_page(&self, path: &[u8], status: StatusCode) -> Option<&Entry> {
+        let path = normalize_path(path).map(|it| it.path).unwrap_or_default();
+        // the request was rejected, so the pages that would be rejected too aren't served
+        let rejected = matches!(status, StatusCode::Unauthorized | StatusCode::Forbidden);
+        let allowed = |route: &str| !rejected || !self.is_protected(route);
+        self.error_pages
+            .find(path.as_ref(), status.into(), allowed)
+            .and_then(|route| self.paths.get(route))
+            .filter(|page| page.content.is_some())
+    }
 }
 
 pub struct Entry {
+    /// Headers of the identity representation.
     pub headers: Vec<Line>,
+    /// Identity (uncompressed) representation.
     pub content: Option<Bytes>,
+    /// Alternative representations, in order of preference.
+    pub encoded: Vec<EncodedContent>,
     pub etag: Option<String>,
+    /// Modification time of the zip entry, in seconds since the unix epoch.
+    pub last_modified: Option<u64>,
+}
+
+impl Entry {
+    /// Selects the representation matching the accept-encoding request header value.
+    /// None means that the identity representation should be sent.
+    pub fn encoded(&self, accept_encoding: Option<&[u8]>) -> Option<&EncodedContent> {
+        preferred_encoding(accept_encoding, &self.encoded)
+    }
+    /// Response headers for the selected representation.
+    pub fn headers<'a>(
+        &'a self,
+        encoded: Option<&'a EncodedContent>,
+    ) -> impl Iterator<Item = &'a Line> {
+        self.headers
+            .iter()
+            .filter(move |&line| encoded.is_none() || line.key != CONTENT_LENGTH)
+            .chain(encoded.into_iter().flat_map(|it| it.headers.iter()))
+    }
+    /// Response body for the selected representation.
+    pub fn body(&self, encoded: Option<&EncodedContent>) -> Option<Bytes> {
+        encoded
+            .map(|it| it.content.clone())
+            .or_else(|| self.content.clone())
+    }
+}
+
+pub struct EncodedContent {
+    pub encoding: Encoding,
+    /// content-length and content-encoding headers.
+    pub headers: Vec<Line>,
+    pub content: Bytes,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+    /// Only available with the `zstd` feature.
+    Zstd,
+}
+
+impl Encoding {
+    /// Content coding token, as used in accept-encoding and content-encoding headers.
+    pub fn token(&self) -> &'static [u8] {
+        match self {
+            Encoding::Brotli => b"br",
+            Encoding::Gzip => b"gzip",
+            Encoding::Zstd => b"zstd",
+        }
+    }
 }
 
 pub trait HeaderSelector {
@@ -110,29 +408,51 @@ pub struct HeadersAndCompression {
     pub redirection: bool,
 }
 
+// Compression that still needs to be done for an entry, and the position of the result in the
+// encoded representations.
+pub(crate) struct PendingCompression {
+    pub(crate) position: usize,
+    pub(crate) encoding: Encoding,
+    pub(crate) compression: Compression,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn build_entry(
-    cursor: &mut Cursor<&[u8]>,
+    files: &mut dyn Files,
     zip_prefix: &str,
-    entry: &ZipCDEntry,
-    entries: &[ZipCDEntry],
+    path_prefix: &str,
+    name: &str,
     header_selector: &dyn HeaderSelector,
+    compression_selector: &dyn CompressionSelector,
+    hidden_paths: &HiddenPaths,
+    url_style: UrlStyle,
     previous: Option<&Handler>,
-) -> Result<Option<(String, Entry)>> {
-    let name = String::from_utf8(entry.file_name_raw.clone())?;
+) -> Result<Option<(String, Entry, Vec<PendingCompression>)>> {
     trace!(entry_name = name);
     if !name.starts_with(zip_prefix) {
         trace!("entry skipped (doesn't start with zip prefix)");
         return Ok(None);
     }
-    let filename = filename(&name);
-    if filename.starts_with('.') || name.starts_with('.') || name.contains("/.") {
+    let filename = filename(name);
+    let relative_name = relative_name(zip_prefix, name);
+    if is_hidden(relative_name) && !hidden_paths.is_allowed(relative_name) {
         trace!("entry skipped");
         return Ok(None);
     }
-    let extension = extension(filename);
+    let extension = if !filename.contains('.') && relative_name.starts_with(ACME_CHALLENGE) {
+        // acme http-01 challenge tokens don't have an extension
+        "txt"
+    } else {
+        extension(filename)
+    };
+    // pre-compressed representations are served with the entry they belong to
     if extension == "br" {
         return Ok(None);
     };
+    #[cfg(feature = "zstd")]
+    if extension == "zst" {
+        return Ok(None);
+    };
     trace!(extension = extension);
     if let Some(HeadersAndCompression {
         mut headers,
@@ -140,116 +460,1835 @@ pub(crate) fn build_entry(
         redirection,
     }) = header_selector.headers_for_extension(filename, extension)
     {
-        let path = path(zip_prefix, &name);
+        let path = path(zip_prefix, name);
         debug!(unprefixed_path = path);
-        let zip_file_header = ZipLocalFileHeader::from_central_directory(cursor, entry)?;
-        let crc32 = zip_file_header.crc32;
+        let Some(file) = files.read(name)? else {
+            return Ok(None);
+        };
+        let crc32 = file.crc32;
         let etag = if headers.iter().any(|it| it.key == CACHE_CONTROL) {
-            let etag = format!("{crc32:x}");
+            let etag = format!("\"{crc32:x}\"");
             trace!(etag = etag.as_str());
             headers.push(Line::with_owned_value(ETAG, etag.as_bytes().to_vec()));
             Some(etag)
         } else {
             None
         };
-        let content = Some(if compressible {
-            let compressed_name = format!("{name}.br");
-            let compressed_name_raw = compressed_name.as_bytes();
-            if let Some(entry) = entries.iter().find_map(|entry| {
-                if entry.file_name_raw == compressed_name_raw {
-                    let zip_file_header =
-                        ZipLocalFileHeader::from_central_directory(cursor, entry).ok()?;
-                    let decompressed = decompress_entry(zip_file_header).ok()?;
-                    if let Some(uncompressed_crc32) =
-                        brotli_decompressed_crc32(decompressed.as_ref())
-                    {
-                        if uncompressed_crc32 == crc32 {
-                            Some(decompressed)
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            }) {
-                entry
-            } else if let Some(entry) = previous.and_then(|it| {
-                it.paths
-                    .get(&compressed_name)
-                    .filter(|&entry| entry.etag == etag)
-            }) {
-                entry.content.clone().unwrap()
+        let last_modified = etag.as_ref().and(file.last_modified);
+        if let Some(last_modified) = last_modified {
+            headers.push(Line::with_owned_value(
+                LAST_MODIFIED,
+                format_http_date(last_modified).into_bytes(),
+            ));
+        }
+        let content = file.content;
+        // the deflate stream stored in the zip can be served as is with a gzip wrapper
+        let gzip = file
+            .deflate
+            .filter(|_| compressible && !redirection)
+            .map(|it| Bytes::from(gzip_from_deflate(&it, crc32, content.len() as u32)));
+        if redirection {
+            headers.push(Line::with_slice_value(CONTENT_LENGTH, b"0"));
+            let end = content
+                .iter()
+                .position(|&b| b.is_ascii_whitespace())
+                .unwrap_or(content.len());
+            headers.push(Line::with_owned_value(LOCATION, content[..end].into()));
+            return Ok(Some((
+                path,
+                Entry {
+                    headers,
+                    content: None,
+                    encoded: vec![],
+                    etag,
+                    last_modified,
+                },
+                vec![],
+            )));
+        }
+        headers.push(Line::with_owned_value(
+            CONTENT_LENGTH,
+            format!("{}", content.len()).into_bytes(),
+        ));
+        headers.push(Line::with_array_ref_value(ACCEPT_RANGES, b"bytes"));
+        let mut pending = vec![];
+        let encoded = if compressible {
+            headers.push(Line::with_array_ref_value(VARY, b"accept-encoding"));
+            let previous = previous
+                .and_then(|it| {
+                    it.paths.get(&entry_route(
+                        url_style,
+                        path_prefix,
+                        zip_prefix,
+                        name,
+                        &path,
+                    ))
+                })
+                .filter(|&entry| etag.is_some() && entry.etag == etag);
+            let mut encoded = Vec::with_capacity(3);
+            #[cfg(feature = "zstd")]
+            if let Some(zstd) = precompressed(
+                files,
+                &format!("{name}.zst"),
+                crc32,
+                zstd_decompressed_crc32,
+            )
+            .or_else(|| previously_encoded(previous, Encoding::Zstd))
+            {
+                encoded.push(encoded_content(Encoding::Zstd, zstd));
             } else {
-                debug!("brotli {path}", path = path);
-                let compressed_size = zip_file_header.compressed_size as usize;
-                Bytes::from(compress_brotli(
-                    decompress_entry(zip_file_header)?.as_ref(),
-                    compressed_size,
-                ))
+                debug!("zstd {path}", path = path);
+                pending.push(PendingCompression {
+                    position: encoded.len(),
+                    encoding: Encoding::Zstd,
+                    compression: Compression::Zstd,
+                });
+                encoded.push(encoded_content(Encoding::Zstd, Bytes::new()));
             }
-        } else {
-            decompress_entry(zip_file_header)?
-        });
-        if let Some(content) = content {
-            if redirection {
-                headers.push(Line::with_slice_value(CONTENT_LENGTH, b"0"));
-                let end = content
-                    .iter()
-                    .position(|&b| b.is_ascii_whitespace())
-                    .unwrap_or(content.len());
-                headers.push(Line::with_owned_value(LOCATION, content[..end].into()));
-                Ok(Some((
-                    path,
-                    Entry {
-                        headers,
-                        content: None,
-                        etag,
-                    },
-                )))
+            if let Some(brotli) = precompressed(
+                files,
+                &format!("{name}.br"),
+                crc32,
+                brotli_decompressed_crc32,
+            )
+            .or_else(|| previously_encoded(previous, Encoding::Brotli))
+            {
+                encoded.push(encoded_content(Encoding::Brotli, brotli));
             } else {
-                headers.push(Line::with_owned_value(
-                    CONTENT_LENGTH,
-                    format!("{}", content.len()).into_bytes(),
-                ));
-                if compressible {
-                    headers.push(Line::with_array_ref_value(CONTENT_ENCODING, b"br"));
-                }
-                Ok(Some((
-                    path,
-                    Entry {
-                        headers,
-                        content: Some(content),
-                        etag,
-                    },
-                )))
+                let params = compression_selector.brotli_params(filename, extension, content.len());
+                debug!("brotli {path} {params:?}", path = path);
+                pending.push(PendingCompression {
+                    position: encoded.len(),
+                    encoding: Encoding::Brotli,
+                    compression: Compression::Brotli(params),
+                });
+                encoded.push(encoded_content(Encoding::Brotli, Bytes::new()));
+            }
+            if let Some(gzip) = gzip {
+                encoded.push(encoded_content(Encoding::Gzip, gzip));
             }
+            encoded
         } else {
-            Ok(None)
-        }
+            vec![]
+        };
+        Ok(Some((
+            path,
+            Entry {
+                headers,
+                content: Some(content),
+                encoded,
+                etag,
+                last_modified,
+            },
+            pending,
+        )))
     } else {
         Ok(None)
     }
 }
 
+// Finds the pre-compressed version of an entry in the source, and checks that it matches
+// the crc32 of the uncompressed entry.
+fn precompressed(
+    files: &mut dyn Files,
+    compressed_name: &str,
+    crc32: u32,
+    decompressed_crc32: fn(&[u8]) -> Option<u32>,
+) -> Option<Bytes> {
+    let compressed = files.read(compressed_name).ok()??.content;
+    if decompressed_crc32(compressed.as_ref())? == crc32 {
+        Some(compressed)
+    } else {
+        debug!("{compressed_name} doesn't match the uncompressed entry");
+        None
+    }
+}
+
+fn previously_encoded(previous: Option<&Entry>, encoding: Encoding) -> Option<Bytes> {
+    previous?
+        .encoded
+        .iter()
+        .find(|it| it.encoding == encoding)
+        .map(|it| it.content.clone())
+}
+
+pub(crate) fn encoded_content(encoding: Encoding, content: Bytes) -> EncodedContent {
+    EncodedContent {
+        encoding,
+        headers: vec![
+            Line::with_owned_value(CONTENT_LENGTH, format!("{}", content.len()).into_bytes()),
+            Line::with_slice_value(CONTENT_ENCODING, encoding.token()),
+        ],
+        content,
+    }
+}
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    #[cfg(feature = "auth")]
+    use crate::auth::AuthPolicy;
+    use crate::builder::UrlStyle;
+    use crate::cors::{AllowedOrigins, CorsPolicy};
     use crate::github::zip_download_commit_url;
-    use crate::http::headers::CONTENT_TYPE;
+    #[cfg(feature = "auth")]
+    use crate::http::headers::WWW_AUTHENTICATE;
+    use crate::http::headers::{
+        ALLOW, CONTENT_RANGE, CONTENT_TYPE, COOP, CORP, CORS, CORS_ALLOW_METHODS, CORS_MAX_AGE,
+        CSP, HSTS, PERMISSIONS_POLICY, REFERRER_POLICY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+    };
+    #[cfg(feature = "auth")]
+    use crate::signed_urls::sign_url;
+    use crate::source::Layers;
+    use crate::spa::FallbackRule;
+    #[cfg(feature = "swappable")]
+    use crate::swappable::SwappableHandler;
+    use crate::types::SecurityHeaders;
     use reqwest::blocking::Client;
+    use std::io::Cursor;
+    #[cfg(feature = "auth")]
+    use std::time::Duration;
     use test_tracing::test;
 
     const COMMIT_HASH: &str = "cf874829749d85c92eeeabae44ed8050864f400f";
 
+    pub(crate) fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        zip_with_method(files, zip::CompressionMethod::Deflated)
+    }
+
+    pub(crate) fn zip_with_method(
+        files: &[(&str, &[u8])],
+        method: zip::CompressionMethod,
+    ) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for &(name, content) in files {
+            writer
+                .start_file(
+                    name,
+                    zip::write::SimpleFileOptions::default().compression_method(method),
+                )
+                .unwrap();
+            std::io::Write::write_all(&mut writer, content).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    pub(crate) struct TestRequest {
+        pub(crate) method: &'static [u8],
+        pub(crate) path: &'static str,
+        pub(crate) headers: Vec<(&'static [u8], &'static [u8])>,
+    }
+
+    pub(crate) struct TestResponse {
+        pub(crate) status: u16,
+        pub(crate) headers: Vec<(Vec<u8>, Vec<u8>)>,
+        pub(crate) body: Option<Bytes>,
+    }
+
+    impl TestResponse {
+        pub(crate) fn header(&self, key: &[u8]) -> Option<&[u8]> {
+            self.headers
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.as_slice())
+        }
+    }
+
+    impl TestRequest {
+        pub(crate) fn get(path: &'static str) -> Self {
+            Self {
+                method: method::GET,
+                path,
+                headers: vec![],
+            }
+        }
+        pub(crate) fn with_header(mut self, key: &'static [u8], value: &'static [u8]) -> Self {
+            self.headers.push((key, value));
+            self
+        }
+    }
+
+    impl Request<TestResponse> for TestRequest {
+        fn method(&self) -> &[u8] {
+            self.method
+        }
+        fn path(&self) -> &[u8] {
+            self.path.as_bytes()
+        }
+        fn first_header_value(&self, key: &'static [u8]) -> Option<&[u8]> {
+            self.headers
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| *v)
+        }
+        fn response<'b>(
+            self,
+            code: StatusCode,
+            headers: impl Iterator<Item = &'b Line>,
+            body: Option<Bytes>,
+        ) -> TestResponse {
+            TestResponse {
+                status: code.into(),
+                headers: headers
+                    .map(|line| (line.name().to_vec(), line.value.as_ref().to_vec()))
+                    .collect(),
+                body,
+            }
+        }
+    }
+
+    const HTML: &[u8] = b"<!DOCTYPE html><html><body><p>Hello, world!</p></body></html>";
+
+    #[test]
+    fn identity_without_accept_encoding() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[("index.html", HTML)]))
+            .try_build()
+            .unwrap();
+        let response = handler.handle(TestRequest::get("/"));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.as_deref(), Some(HTML));
+        assert_eq!(response.header(CONTENT_ENCODING), None);
+        assert_eq!(
+            response.header(CONTENT_LENGTH),
+            Some(format!("{}", HTML.len()).as_bytes())
+        );
+        assert_eq!(response.header(VARY), Some(b"accept-encoding".as_slice()));
+        let response =
+            handler.handle(TestRequest::get("/").with_header(ACCEPT_ENCODING, b"deflate, br;q=0"));
+        assert_eq!(response.body.as_deref(), Some(HTML));
+        assert_eq!(response.header(CONTENT_ENCODING), None);
+    }
+
+    #[test]
+    fn brotli_with_accept_encoding() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[("index.html", HTML)]))
+            .try_build()
+            .unwrap();
+        let response =
+            handler.handle(TestRequest::get("/").with_header(ACCEPT_ENCODING, b"gzip, br"));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header(CONTENT_ENCODING), Some(b"br".as_slice()));
+        assert_eq!(response.header(VARY), Some(b"accept-encoding".as_slice()));
+        let body = response.body.clone().unwrap();
+        assert_eq!(
+            response.header(CONTENT_LENGTH),
+            Some(format!("{}", body.len()).as_bytes())
+        );
+        assert_eq!(
+            response
+                .headers
+                .iter()
+                .filter(|(key, _)| *key == CONTENT_LENGTH)
+                .count(),
+            1
+        );
+        assert_eq!(
+            brotli_decompressed_crc32(body.as_ref()),
+            Some(crc32fast::hash(HTML))
+        );
+    }
+
+    #[test]
+    fn gzip_from_deflated_entry() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[("index.html", HTML)]))
+            .try_build()
+            .unwrap();
+        let response =
+            handler.handle(TestRequest::get("/").with_header(ACCEPT_ENCODING, b"gzip, deflate"));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header(CONTENT_ENCODING), Some(b"gzip".as_slice()));
+        let body = response.body.clone().unwrap();
+        assert_eq!(
+            response.header(CONTENT_LENGTH),
+            Some(format!("{}", body.len()).as_bytes())
+        );
+        assert_eq!(&body[..3], &[0x1f, 0x8b, 8]);
+        let trailer = &body[body.len() - 8..];
+        assert_eq!(&trailer[..4], &crc32fast::hash(HTML).to_le_bytes());
+        assert_eq!(&trailer[4..], &(HTML.len() as u32).to_le_bytes());
+        assert_eq!(
+            inflate::inflate_bytes(&body[10..body.len() - 8]).unwrap(),
+            HTML
+        );
+        let response = handler
+            .handle(TestRequest::get("/").with_header(ACCEPT_ENCODING, b"gzip, deflate, br"));
+        assert_eq!(response.header(CONTENT_ENCODING), Some(b"br".as_slice()));
+    }
+
+    #[test]
+    fn no_gzip_for_stored_entry() {
+        let handler = Handler::builder()
+            .with_zip(zip_with_method(
+                &[("index.html", HTML)],
+                zip::CompressionMethod::Stored,
+            ))
+            .try_build()
+            .unwrap();
+        assert!(
+            handler
+                .entry("/")
+                .unwrap()
+                .encoded
+                .iter()
+                .all(|it| it.encoding != Encoding::Gzip)
+        );
+        let response = handler.handle(TestRequest::get("/").with_header(ACCEPT_ENCODING, b"gzip"));
+        assert_eq!(response.body.as_deref(), Some(HTML));
+        assert_eq!(response.header(CONTENT_ENCODING), None);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[("index.html", HTML)]))
+            .try_build()
+            .unwrap();
+        let response = handler
+            .handle(TestRequest::get("/").with_header(ACCEPT_ENCODING, b"gzip, deflate, br, zstd"));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header(CONTENT_ENCODING), Some(b"zstd".as_slice()));
+        let body = response.body.unwrap();
+        assert_eq!(zstd::decode_all(body.as_ref()).unwrap(), HTML);
+        let response =
+            handler.handle(TestRequest::get("/").with_header(ACCEPT_ENCODING, b"gzip, br"));
+        assert_eq!(response.header(CONTENT_ENCODING), Some(b"br".as_slice()));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn precompressed_zstd() {
+        let precompressed = zstd::encode_all(HTML, 3).unwrap();
+        let other = zstd::encode_all(b"other".as_slice(), 3).unwrap();
+        let handler = Handler::builder()
+            .with_zip(zip(&[
+                ("index.html", HTML),
+                ("index.html.zst", &precompressed),
+                ("other.html", HTML),
+                ("other.html.zst", &other),
+            ]))
+            .try_build()
+            .unwrap();
+        assert!(!handler.paths.contains_key("/index.html.zst"));
+        let zstd = |path| {
+            handler
+                .entry(path)
+                .unwrap()
+                .encoded
+                .iter()
+                .find(|it| it.encoding == Encoding::Zstd)
+                .unwrap()
+                .content
+                .clone()
+        };
+        assert_eq!(zstd("/").as_ref(), precompressed.as_slice());
+        assert_ne!(zstd("/other").as_ref(), other.as_slice());
+        assert_eq!(zstd::decode_all(zstd("/other").as_ref()).unwrap(), HTML);
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn zst_download() {
+        struct Downloads;
+        impl HeaderSelector for Downloads {
+            fn headers_for_extension(
+                &self,
+                _filename: &str,
+                _extension: &str,
+            ) -> Option<HeadersAndCompression> {
+                Some(HeadersAndCompression {
+                    headers: vec![Line::with_slice_value(CACHE_CONTROL, b"no-cache")],
+                    compressible: false,
+                    redirection: false,
+                })
+            }
+            fn error_headers(&self) -> &'static [Line] {
+                &[]
+            }
+        }
+        let handler = Handler::builder()
+            .with_custom_header_selector(&Downloads)
+            .with_zip(zip(&[
+                ("archive.zst", b"zstd frames"),
+                ("archive.br", b"br"),
+            ]))
+            .try_build()
+            .unwrap();
+        let response = handler.handle(TestRequest::get("/archive.zst"));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.as_deref(), Some(b"zstd frames".as_slice()));
+        assert!(handler.entry("/archive.br").is_none());
+    }
+
+    #[test]
+    fn parallel_compression() {
+        let files = (0..16)
+            .map(|i| (format!("page{i}.html"), HTML.repeat(i + 1)))
+            .collect::<Vec<_>>();
+        let files = files
+            .iter()
+            .map(|(name, content)| (name.as_str(), content.as_slice()))
+            .collect::<Vec<_>>();
+        let sequential = Handler::builder()
+            .with_zip(zip(&files))
+            .try_build()
+            .unwrap();
+        let parallel = Handler::builder()
+            .with_zip(zip(&files))
+            .with_compression_threads(4)
+            .try_build()
+            .unwrap();
+        assert_eq!(sequential.paths.len(), parallel.paths.len());
+        for (path, entry) in &sequential.paths {
+            let other = parallel.entry(path).unwrap();
+            assert_eq!(entry.content, other.content);
+            assert_eq!(entry.encoded.len(), other.encoded.len());
+            for (a, b) in entry.encoded.iter().zip(other.encoded.iter()) {
+                assert_eq!(a.encoding, b.encoding);
+                assert_eq!(a.content, b.content);
+                assert!(!a.content.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn brotli_params() {
+        use crate::builder::{BrotliParams, CompressionRules};
+        let content = HTML.repeat(64);
+        let brotli = |handler: &Handler| {
+            handler
+                .entry("/")
+                .unwrap()
+                .encoded
+                .iter()
+                .find(|it| it.encoding == Encoding::Brotli)
+                .unwrap()
+                .content
+                .clone()
+        };
+        let default = Handler::builder()
+            .with_zip(zip(&[("index.html", &content)]))
+            .try_build()
+            .unwrap();
+        let fast = Handler::builder()
+            .with_zip(zip(&[("index.html", &content)]))
+            .with_compression_selector(CompressionRules::default().with_extension(
+                "html",
+                BrotliParams {
+                    quality: 0,
+                    window: 10,
+                },
+            ))
+            .try_build()
+            .unwrap();
+        assert_ne!(brotli(&default), brotli(&fast));
+        assert_eq!(
+            brotli_decompressed_crc32(brotli(&fast).as_ref()),
+            Some(crc32fast::hash(&content))
+        );
+    }
+
+    #[test]
+    fn not_compressible() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[("image.png", b"not really a png")]))
+            .try_build()
+            .unwrap();
+        let response =
+            handler.handle(TestRequest::get("/image.png").with_header(ACCEPT_ENCODING, b"br"));
+        assert_eq!(response.status, 200);
+        assert_eq!(
+            response.body.as_deref(),
+            Some(b"not really a png".as_slice())
+        );
+        assert_eq!(response.header(CONTENT_ENCODING), None);
+        assert_eq!(response.header(VARY), None);
+    }
+
+    #[test]
+    fn single_range() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[("index.html", HTML)]))
+            .try_build()
+            .unwrap();
+        let response = handler.handle(
+            TestRequest::get("/")
+                .with_header(RANGE, b"bytes=0-14")
+                .with_header(ACCEPT_ENCODING, b"br"),
+        );
+        assert_eq!(response.status, 206);
+        assert_eq!(response.body.as_deref(), Some(&HTML[..15]));
+        assert_eq!(
+            response.header(CONTENT_RANGE),
+            Some(format!("bytes 0-14/{}", HTML.len()).as_bytes())
+        );
+        assert_eq!(response.header(CONTENT_LENGTH), Some(b"15".as_slice()));
+        assert_eq!(response.header(CONTENT_ENCODING), None);
+        let response = handler.handle(TestRequest::get("/"));
+        assert_eq!(response.header(ACCEPT_RANGES), Some(b"bytes".as_slice()));
+    }
+
+    #[test]
+    fn multiple_ranges() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[("index.html", HTML)]))
+            .try_build()
+            .unwrap();
+        let response = handler.handle(TestRequest::get("/").with_header(RANGE, b"bytes=0-8, -7"));
+        assert_eq!(response.status, 206);
+        let content_type = response.header(CONTENT_TYPE).unwrap();
+        assert!(content_type.starts_with(b"multipart/byteranges; boundary="));
+        let body = response.body.clone().unwrap();
+        assert_eq!(
+            response.header(CONTENT_LENGTH),
+            Some(format!("{}", body.len()).as_bytes())
+        );
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("content-type: text/html"));
+        assert!(body.contains("\r\n\r\n<!DOCTYPE"));
+        assert!(body.contains(&format!(
+            "bytes {}-{}/{}",
+            HTML.len() - 7,
+            HTML.len() - 1,
+            HTML.len()
+        )));
+        assert!(body.contains("\r\n\r\n</html>\r\n"));
+    }
+
+    #[test]
+    fn range_not_satisfiable() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[("index.html", HTML)]))
+            .try_build()
+            .unwrap();
+        let response = handler.handle(TestRequest::get("/").with_header(RANGE, b"bytes=1000-"));
+        assert_eq!(response.status, 416);
+        assert_eq!(response.body, None);
+        assert_eq!(
+            response.header(CONTENT_RANGE),
+            Some(format!("bytes */{}", HTML.len()).as_bytes())
+        );
+        let response = handler.handle(TestRequest::get("/").with_header(RANGE, b"lines=1-2"));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.as_deref(), Some(HTML));
+    }
+
+    #[test]
+    fn if_range() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[("index.html", HTML)]))
+            .try_build()
+            .unwrap();
+        let etag = handler
+            .handle(TestRequest::get("/"))
+            .header(ETAG)
+            .unwrap()
+            .to_vec();
+        let etag: &'static [u8] = Box::leak(etag.into_boxed_slice());
+        let response = handler.handle(
+            TestRequest::get("/")
+                .with_header(RANGE, b"bytes=0-0")
+                .with_header(IF_RANGE, etag),
+        );
+        assert_eq!(response.status, 206);
+        assert_eq!(response.body.as_deref(), Some(b"<".as_slice()));
+        let response = handler.handle(
+            TestRequest::get("/")
+                .with_header(RANGE, b"bytes=0-0")
+                .with_header(IF_RANGE, b"\"outdated\""),
+        );
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.as_deref(), Some(HTML));
+        let last_modified = handler
+            .handle(TestRequest::get("/"))
+            .header(LAST_MODIFIED)
+            .unwrap()
+            .to_vec();
+        let last_modified: &'static [u8] = Box::leak(last_modified.into_boxed_slice());
+        let response = handler.handle(
+            TestRequest::get("/")
+                .with_header(RANGE, b"bytes=0-0")
+                .with_header(IF_RANGE, last_modified),
+        );
+        assert_eq!(response.status, 206);
+        assert_eq!(response.body.as_deref(), Some(b"<".as_slice()));
+        let response = handler.handle(
+            TestRequest::get("/")
+                .with_header(RANGE, b"bytes=0-0")
+                .with_header(IF_RANGE, b"Sun, 06 Nov 1994 08:49:37 GMT"),
+        );
+        assert_eq!(response.status, 200);
+    }
+
+    #[test]
+    fn if_none_match() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[("index.html", HTML)]))
+            .try_build()
+            .unwrap();
+        let etag = format!("\"{:x}\"", crc32fast::hash(HTML));
+        let response = handler.handle(TestRequest::get("/"));
+        assert_eq!(response.header(ETAG), Some(etag.as_bytes()));
+        let etag: &'static str = etag.leak();
+        for value in [
+            etag.as_bytes(),
+            format!("W/{etag}").leak().as_bytes(),
+            format!("\"outdated\", {etag}").leak().as_bytes(),
+            b"*",
+        ] {
+            let response = handler.handle(TestRequest::get("/").with_header(IF_NONE_MATCH, value));
+            assert_eq!(response.status, 304);
+            assert_eq!(response.body, None);
+            assert_eq!(response.header(CONTENT_LENGTH), None);
+            assert_eq!(response.header(ETAG), Some(etag.as_bytes()));
+        }
+        let response = handler.handle(
+            TestRequest::get("/").with_header(IF_NONE_MATCH, etag.trim_matches('"').as_bytes()),
+        );
+        assert_eq!(response.status, 200);
+    }
+
+    #[test]
+    fn if_match() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[("index.html", HTML)]))
+            .try_build()
+            .unwrap();
+        let etag: &'static str = format!("\"{:x}\"", crc32fast::hash(HTML)).leak();
+        let response = handler.handle(TestRequest::get("/").with_header(IF_MATCH, etag.as_bytes()));
+        assert_eq!(response.status, 200);
+        let response = handler.handle(TestRequest::get("/").with_header(IF_MATCH, b"*"));
+        assert_eq!(response.status, 200);
+        let response = handler.handle(
+            TestRequest::get("/").with_header(IF_MATCH, format!("W/{etag}").leak().as_bytes()),
+        );
+        assert_eq!(response.status, 412);
+        // if-match takes precedence over if-none-match
+        let response = handler.handle(
+            TestRequest::get("/")
+                .with_header(IF_MATCH, b"\"outdated\"")
+                .with_header(IF_NONE_MATCH, etag.as_bytes()),
+        );
+        assert_eq!(response.status, 412);
+    }
+
+    #[test]
+    fn last_modified() {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file(
+                "index.html",
+                zip::write::SimpleFileOptions::default().last_modified_time(
+                    zip::DateTime::from_date_and_time(1994, 11, 6, 8, 49, 36).unwrap(),
+                ),
+            )
+            .unwrap();
+        std::io::Write::write_all(&mut writer, HTML).unwrap();
+        let handler = Handler::builder()
+            .with_zip(writer.finish().unwrap().into_inner())
+            .try_build()
+            .unwrap();
+        let response = handler.handle(TestRequest::get("/"));
+        assert_eq!(
+            response.header(LAST_MODIFIED),
+            Some(b"Sun, 06 Nov 1994 08:49:36 GMT".as_slice())
+        );
+        let response = handler.handle(
+            TestRequest::get("/").with_header(IF_MODIFIED_SINCE, b"Sun, 06 Nov 1994 08:49:36 GMT"),
+        );
+        assert_eq!(response.status, 304);
+        assert_eq!(
+            response.header(LAST_MODIFIED),
+            Some(b"Sun, 06 Nov 1994 08:49:36 GMT".as_slice())
+        );
+        let response = handler.handle(
+            TestRequest::get("/").with_header(IF_MODIFIED_SINCE, b"Sun, 06 Nov 1994 08:49:35 GMT"),
+        );
+        assert_eq!(response.status, 200);
+        let response =
+            handler.handle(TestRequest::get("/").with_header(IF_MODIFIED_SINCE, b"not a date"));
+        assert_eq!(response.status, 200);
+        // if-modified-since is ignored when if-none-match is present
+        let response = handler.handle(
+            TestRequest::get("/")
+                .with_header(IF_MODIFIED_SINCE, b"Sun, 06 Nov 1994 08:49:36 GMT")
+                .with_header(IF_NONE_MATCH, b"\"outdated\""),
+        );
+        assert_eq!(response.status, 200);
+        let response = handler.handle(
+            TestRequest::get("/")
+                .with_header(IF_UNMODIFIED_SINCE, b"Sat, 05 Nov 1994 08:49:36 GMT"),
+        );
+        assert_eq!(response.status, 412);
+        let response = handler.handle(
+            TestRequest::get("/")
+                .with_header(IF_UNMODIFIED_SINCE, b"Sun, 06 Nov 1994 08:49:36 GMT"),
+        );
+        assert_eq!(response.status, 200);
+        // if-unmodified-since is ignored when if-match is present
+        let response = handler.handle(
+            TestRequest::get("/")
+                .with_header(IF_UNMODIFIED_SINCE, b"Sat, 05 Nov 1994 08:49:36 GMT")
+                .with_header(IF_MATCH, b"*"),
+        );
+        assert_eq!(response.status, 200);
+    }
+
+    #[test]
+    fn cors() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[("index.html", HTML), ("fonts/a.woff2", b"woff2")]))
+            .with_cors(
+                "/fonts",
+                CorsPolicy::new(AllowedOrigins::List(vec!["https://a.com".to_string()]))
+                    .with_allowed_headers(["x-requested-with"])
+                    .with_max_age(600),
+            )
+            .try_build()
+            .unwrap();
+        let response = handler
+            .handle(TestRequest::get("/fonts/a.woff2").with_header(ORIGIN, b"https://a.com"));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header(CORS), Some(b"https://a.com".as_slice()));
+        assert_eq!(response.header(VARY), Some(b"origin".as_slice()));
+        let response = handler
+            .handle(TestRequest::get("/fonts/a.woff2").with_header(ORIGIN, b"https://b.com"));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header(CORS), None);
+        let response = handler.handle(TestRequest::get("/").with_header(ORIGIN, b"https://a.com"));
+        assert_eq!(response.header(CORS), None);
+    }
+
+    #[test]
+    fn preflight() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[("index.html", HTML), ("fonts/a.woff2", b"woff2")]))
+            .with_cors(
+                "/",
+                CorsPolicy::new(AllowedOrigins::Exact("https://a.com".to_string()))
+                    .with_max_age(600),
+            )
+            .try_build()
+            .unwrap();
+        let options = |path: &'static str| TestRequest {
+            method: method::OPTIONS,
+            path,
+            headers: vec![],
+        };
+        let response = handler.handle(
+            options("/fonts/a.woff2")
+                .with_header(ORIGIN, b"https://a.com")
+                .with_header(CORS_REQUEST_METHOD, b"GET"),
+        );
+        assert_eq!(response.status, 204);
+        assert_eq!(response.body, None);
+        assert_eq!(response.header(CORS), Some(b"https://a.com".as_slice()));
+        assert_eq!(
+            response.header(CORS_ALLOW_METHODS),
+            Some(b"GET, HEAD".as_slice())
+        );
+        assert_eq!(response.header(CORS_MAX_AGE), Some(b"600".as_slice()));
+        let response = handler.handle(
+            options("/fonts/a.woff2")
+                .with_header(ORIGIN, b"https://a.com")
+                .with_header(CORS_REQUEST_METHOD, b"DELETE"),
+        );
+        assert_eq!(response.status, 204);
+        assert_eq!(response.header(CORS), None);
+        let response = handler.handle(options("/"));
+        assert_eq!(response.status, 204);
+        assert_eq!(
+            response.header(ALLOW),
+            Some(b"GET, HEAD, OPTIONS".as_slice())
+        );
+        let response = handler.handle(options("/missing"));
+        assert_eq!(response.status, 404);
+        let response = handler.handle(TestRequest {
+            method: b"POST",
+            path: "/",
+            headers: vec![],
+        });
+        assert_eq!(response.status, 405);
+    }
+
+    #[test]
+    fn preflight_fallback() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[
+                ("index.html", HTML),
+                ("app/index.html", b"<p>app</p>"),
+                ("_redirects", b"/docs/* /app 200\n/old /app\n"),
+            ]))
+            .with_spa_fallback(SpaFallback::new("/app", "/app"))
+            .with_cors("/", CorsPolicy::new(AllowedOrigins::Any))
+            .try_build()
+            .unwrap();
+        let preflight = |path: &'static str| {
+            handler.handle(
+                TestRequest {
+                    method: method::OPTIONS,
+                    path,
+                    headers: vec![],
+                }
+                .with_header(ORIGIN, b"https://a.com")
+                .with_header(CORS_REQUEST_METHOD, b"GET"),
+            )
+        };
+        let response = preflight("/app/settings/42");
+        assert_eq!(response.status, 204);
+        assert_eq!(response.header(CORS), Some(b"*".as_slice()));
+        let response = preflight("/docs/intro");
+        assert_eq!(response.status, 204);
+        assert_eq!(response.header(CORS), Some(b"*".as_slice()));
+        assert_eq!(preflight("/old").status, 404);
+        assert_eq!(preflight("/app/main.js").status, 404);
+    }
+
+    #[test]
+    fn error_pages() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[
+                ("index.html", HTML),
+                ("404.html", b"<p>not found</p>"),
+                ("docs/index.html", HTML),
+                ("docs/404.html", b"<p>doc not found</p>"),
+                ("4xx.html", b"<p>client error</p>"),
+            ]))
+            .with_error_page("4xx", "4xx.html")
+            .try_build()
+            .unwrap();
+        let response = handler.handle(TestRequest::get("/missing"));
+        assert_eq!(response.status, 404);
+        assert_eq!(
+            response.body.as_deref(),
+            Some(b"<p>not found</p>".as_slice())
+        );
+        assert_eq!(response.header(CONTENT_TYPE), Some(b"text/html".as_slice()));
+        assert_eq!(response.header(CONTENT_LENGTH), Some(b"16".as_slice()));
+        assert_eq!(response.header(ETAG), None);
+        let response = handler.handle(TestRequest::get("/docs/a/missing"));
+        assert_eq!(response.status, 404);
+        assert_eq!(
+            response.body.as_deref(),
+            Some(b"<p>doc not found</p>".as_slice())
+        );
+        let response =
+            handler.handle(TestRequest::get("/missing").with_header(ACCEPT_ENCODING, b"br"));
+        assert_eq!(response.status, 404);
+        assert_eq!(response.header(CONTENT_ENCODING), Some(b"br".as_slice()));
+        assert_eq!(
+            brotli_decompressed_crc32(response.body.as_deref().unwrap()),
+            Some(crc32fast::hash(b"<p>not found</p>"))
+        );
+        let response = handler.handle(TestRequest {
+            method: method::HEAD,
+            path: "/missing",
+            headers: vec![],
+        });
+        assert_eq!(response.status, 404);
+        assert_eq!(response.body, None);
+        let response = handler.handle(TestRequest {
+            method: b"POST",
+            path: "/",
+            headers: vec![],
+        });
+        assert_eq!(response.status, 405);
+        assert_eq!(
+            response.body.as_deref(),
+            Some(b"<p>client error</p>".as_slice())
+        );
+        assert!(
+            response
+                .headers
+                .iter()
+                .any(|(key, value)| *key == ALLOW && value == b"GET, HEAD, OPTIONS")
+        );
+    }
+
+    #[test]
+    fn without_error_page() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[("index.html", HTML)]))
+            .try_build()
+            .unwrap();
+        let response = handler.handle(TestRequest::get("/missing"));
+        assert_eq!(response.status, 404);
+        assert_eq!(response.body, None);
+        assert!(
+            Handler::builder()
+                .with_zip(zip(&[("index.html", HTML)]))
+                .with_error_page("2xx", "2xx.html")
+                .try_build()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn spa_fallback() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[
+                ("index.html", HTML),
+                ("app/index.html", b"<p>app</p>"),
+                ("app/main.js", b"main()"),
+            ]))
+            .with_spa_fallback(SpaFallback::new("/app", "/app"))
+            .try_build()
+            .unwrap();
+        assert!(!handler.paths.contains_key("/app/settings/42"));
+        let accept = b"text/html,application/xhtml+xml,*/*;q=0.8";
+        let response =
+            handler.handle(TestRequest::get("/app/settings/42").with_header(ACCEPT, accept));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.as_deref(), Some(b"<p>app</p>".as_slice()));
+        assert!(
+            response
+                .headers
+                .iter()
+                .any(|(key, value)| *key == VARY && value == b"accept")
+        );
+        let response = handler.handle(TestRequest::get("/app/main.js").with_header(ACCEPT, accept));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.as_deref(), Some(b"main()".as_slice()));
+        let response =
+            handler.handle(TestRequest::get("/app/main.abc.js").with_header(ACCEPT, accept));
+        assert_eq!(response.status, 404);
+        let response = handler.handle(TestRequest::get("/app/settings/42"));
+        assert_eq!(response.status, 404);
+        let response = handler.handle(TestRequest::get("/other").with_header(ACCEPT, accept));
+        assert_eq!(response.status, 404);
+        let response = handler.handle(
+            TestRequest::get("/app/users")
+                .with_header(ACCEPT, accept)
+                .with_header(RANGE, b"bytes=0-2"),
+        );
+        assert_eq!(response.status, 206);
+        assert_eq!(response.body.as_deref(), Some(b"<p>".as_slice()));
+    }
+
+    #[test]
+    fn spa_fallback_rules() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[("index.html", HTML), ("main.js", b"main()")]))
+            .with_spa_fallback(SpaFallback::new("/", "/").with_rule(FallbackRule::Extensionless))
+            .try_build()
+            .unwrap();
+        let response = handler.handle(TestRequest::get("/settings/42"));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.as_deref(), Some(HTML));
+        assert_eq!(response.header(VARY), Some(b"accept-encoding".as_slice()));
+        let response = handler.handle(TestRequest::get("/main.abc.js"));
+        assert_eq!(response.status, 404);
+        let handler = Handler::builder()
+            .with_zip(zip(&[("index.html", HTML)]))
+            .with_spa_fallback(SpaFallback::new("/", "/").with_rule(FallbackRule::Any))
+            .try_build()
+            .unwrap();
+        let response = handler.handle(TestRequest::get("/main.abc.js"));
+        assert_eq!(response.status, 200);
+        assert!(
+            Handler::builder()
+                .with_zip(zip(&[("index.html", HTML)]))
+                .with_spa_fallback(SpaFallback::new("/app", "/app/index"))
+                .try_build()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn directory_index() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[
+                ("index.html", HTML),
+                ("docs/index.html", b"<p>docs</p>"),
+                ("docs/a/index.html", b"<p>a</p>"),
+            ]))
+            .try_build()
+            .unwrap();
+        let response = handler.handle(TestRequest::get("/docs"));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.as_deref(), Some(b"<p>docs</p>".as_slice()));
+        let response = handler.handle(TestRequest::get("/docs/"));
+        assert_eq!(response.status, 308);
+        assert_eq!(response.header(LOCATION), Some(b"/docs".as_slice()));
+        let response = handler.handle(TestRequest::get("/docs/a"));
+        assert_eq!(response.body.as_deref(), Some(b"<p>a</p>".as_slice()));
+        assert_eq!(handler.handle(TestRequest::get("/docs/index")).status, 404);
+        let error = Handler::builder()
+            .with_zip(zip(&[("docs.html", HTML), ("docs/index.html", HTML)]))
+            .try_build()
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "route collision: docs.html and docs/index.html are both served at /docs"
+        );
+        // only pages collide, a redirect file can sit next to a page
+        let handler = Handler::builder()
+            .with_zip(zip(&[
+                ("index.html", HTML),
+                ("about.html", HTML),
+                ("about.308", b"/"),
+            ]))
+            .try_build()
+            .unwrap();
+        assert!(handler.paths.contains_key("/about"));
+    }
+
+    #[test]
+    fn url_styles() {
+        let files: &[(&str, &[u8])] = &[
+            ("index.html", HTML),
+            ("a/b.html", b"<p>b</p>"),
+            ("docs/index.html", b"<p>docs</p>"),
+        ];
+        let handler = Handler::builder().with_zip(zip(files)).try_build().unwrap();
+        assert_eq!(handler.handle(TestRequest::get("/a/b")).status, 200);
+        assert_eq!(handler.handle(TestRequest::get("/a/b/")).status, 308);
+        assert_eq!(handler.handle(TestRequest::get("/a/b.html")).status, 404);
+        assert_eq!(handler.handle(TestRequest::get("/index.html")).status, 404);
+        let handler = Handler::builder()
+            .with_zip(zip(files))
+            .with_url_style(UrlStyle::Extension)
+            .try_build()
+            .unwrap();
+        let response = handler.handle(TestRequest::get("/a/b.html"));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.as_deref(), Some(b"<p>b</p>".as_slice()));
+        let response = handler.handle(TestRequest::get("/docs/"));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.as_deref(), Some(b"<p>docs</p>".as_slice()));
+        assert_eq!(handler.handle(TestRequest::get("/")).status, 200);
+        assert_eq!(handler.handle(TestRequest::get("/a/b")).status, 404);
+        assert_eq!(handler.handle(TestRequest::get("/a/b/")).status, 404);
+        assert_eq!(handler.handle(TestRequest::get("/docs")).status, 404);
+        let handler = Handler::builder()
+            .with_zip(zip(files))
+            .with_root_prefix("/site")
+            .with_url_style(UrlStyle::CleanWithRedirects)
+            .try_build()
+            .unwrap();
+        assert_eq!(handler.handle(TestRequest::get("/site/a/b")).status, 200);
+        for (path, location) in [
+            ("/site/a/b.html", "/site/a/b"),
+            ("/site/a/b/", "/site/a/b"),
+            ("/site/docs/index.html", "/site/docs"),
+            ("/site/docs/", "/site/docs"),
+            ("/site/index.html", "/site"),
+            ("/site/", "/site"),
+        ] {
+            let response = handler.handle(TestRequest::get(path));
+            assert_eq!(response.status, 308);
+            assert_eq!(response.header(LOCATION), Some(location.as_bytes()));
+        }
+    }
+
+    #[test]
+    fn path_normalization() {
+        let files: &[(&str, &[u8])] = &[
+            ("index.html", HTML),
+            ("café.png", b"png"),
+            ("a/b.txt", b"b"),
+            ("404.html", b"<p>not found</p>"),
+        ];
+        let handler = Handler::builder().with_zip(zip(files)).try_build().unwrap();
+        for path in [
+            "/caf%C3%A9.png",
+            "//caf%c3%a9.png",
+            "/a/./b.txt",
+            "/a//b.txt",
+            "/c/../a/b.txt",
+            "/a/%2E/b.txt?x=1",
+        ] {
+            assert_eq!(handler.handle(TestRequest::get(path)).status, 200, "{path}");
+        }
+        for path in [
+            "/a%2Fb.txt",
+            "/a/b.txt%00",
+            "/../a/b.txt",
+            "/a/../../a/b.txt",
+            "a/b.txt",
+        ] {
+            let response = handler.handle(TestRequest::get(path));
+            assert_eq!(response.status, 400, "{path}");
+            assert_eq!(response.body, None);
+        }
+        assert_eq!(
+            handler
+                .handle(TestRequest::get("/a/../missing"))
+                .body
+                .as_deref(),
+            Some(b"<p>not found</p>".as_slice())
+        );
+        let handler = Handler::builder()
+            .with_zip(zip(files))
+            .with_canonical_redirects()
+            .try_build()
+            .unwrap();
+        let response = handler.handle(TestRequest::get("/c/../a//b.txt?x=1"));
+        assert_eq!(response.status, 308);
+        assert_eq!(response.header(LOCATION), Some(b"/a/b.txt?x=1".as_slice()));
+        assert_eq!(
+            handler.handle(TestRequest::get("/caf%C3%A9.png")).status,
+            200
+        );
+        assert_eq!(handler.handle(TestRequest::get("/a/b.txt?x=1")).status, 200);
+    }
+
+    #[test]
+    fn redirects() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[
+                ("index.html", HTML),
+                ("app/index.html", b"<p>app</p>"),
+                ("new.html", b"<p>new</p>"),
+                (
+                    "_redirects",
+                    b"/old /new\n/blog/:slug /posts/:slug 302\n/app/* /app 200\n/new /other\n",
+                ),
+            ]))
+            .with_root_prefix("/site")
+            .try_build()
+            .unwrap();
+        assert!(!handler.paths.contains_key("/site/_redirects"));
+        let response = handler.handle(TestRequest::get("/site/old"));
+        assert_eq!(response.status, 301);
+        assert_eq!(response.header(LOCATION), Some(b"/site/new".as_slice()));
+        let response = handler.handle(TestRequest::get("/site/blog/hello"));
+        assert_eq!(response.status, 302);
+        assert_eq!(
+            response.header(LOCATION),
+            Some(b"/site/posts/hello".as_slice())
+        );
+        let response = handler.handle(TestRequest::get("/site/app/settings/42"));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.as_deref(), Some(b"<p>app</p>".as_slice()));
+        // entries take precedence over the rules
+        let response = handler.handle(TestRequest::get("/site/new"));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.as_deref(), Some(b"<p>new</p>".as_slice()));
+        assert_eq!(
+            handler.handle(TestRequest::get("/site/missing")).status,
+            404
+        );
+        let error = Handler::builder()
+            .with_zip(zip(&[("index.html", HTML), ("_redirects", b"/* /app 200")]))
+            .try_build()
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "redirect target not found: /app");
+        let error = Handler::builder()
+            .with_zip(zip(&[("index.html", HTML), ("_redirects", b"/a /b 404")]))
+            .try_build()
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "invalid _redirects rule on line 1: /a /b 404"
+        );
+    }
+
+    #[test]
+    fn header_rules() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[
+                ("index.html", HTML),
+                ("admin/users.html", HTML),
+                (
+                    "_headers",
+                    b"/admin/*\n  ! X-Frame-Options\n  Cache-Control: no-store\n",
+                ),
+            ]))
+            .try_build()
+            .unwrap();
+        assert!(!handler.paths.contains_key("/_headers"));
+        let response = handler.handle(TestRequest::get("/admin/users"));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header(X_FRAME_OPTIONS), None);
+        assert_eq!(response.header(CACHE_CONTROL), Some(b"no-store".as_slice()));
+        let response = handler.handle(TestRequest::get("/"));
+        assert_eq!(response.header(X_FRAME_OPTIONS), Some(b"DENY".as_slice()));
+        assert!(
+            Handler::builder()
+                .with_zip(zip(&[
+                    ("index.html", HTML),
+                    ("_headers", b"/*\n  Vary: *\n")
+                ]))
+                .try_build()
+                .is_err()
+        );
+    }
+
+    #[cfg(feature = "auth")]
+    #[test]
+    fn auth() {
+        // hash_password("secret", b"zip-static-salt", 1000)
+        let policy = AuthPolicy::new("internal")
+            .with_user(
+                "admin",
+                "$pbkdf2-sha256$i=1000$emlwLXN0YXRpYy1zYWx0$cx2c6+CZ1tlrvztMZWC4g7XZ65WRL6Jj24xX8YHdPt0",
+            )
+            .with_bearer_token("token");
+        let handler = Handler::builder()
+            .with_zip(zip(&[("index.html", HTML), ("internal/docs.html", HTML)]))
+            .with_auth("/internal", policy)
+            .try_build()
+            .unwrap();
+        let response = handler.handle(TestRequest::get("/internal/docs"));
+        assert_eq!(response.status, 401);
+        assert_eq!(response.body, None);
+        let challenges = response
+            .headers
+            .iter()
+            .filter(|(key, _)| *key == WWW_AUTHENTICATE)
+            .map(|(_, value)| value.as_slice())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            challenges,
+            [
+                b"Basic realm=\"internal\", charset=\"UTF-8\"".as_slice(),
+                b"Bearer realm=\"internal\"".as_slice()
+            ]
+        );
+        // missing paths under the prefix are protected too
+        let response = handler.handle(TestRequest::get("/internal/missing"));
+        assert_eq!(response.status, 401);
+        let response = handler.handle(
+            TestRequest::get("/internal/docs")
+                .with_header(AUTHORIZATION, b"Basic YWRtaW46c2VjcmV0"),
+        );
+        assert_eq!(response.status, 200);
+        assert_eq!(
+            response.header(CACHE_CONTROL),
+            Some(b"private,max-age=3600,stale-if-error=3600".as_slice())
+        );
+        let response = handler
+            .handle(TestRequest::get("/internal/docs").with_header(AUTHORIZATION, b"Bearer token"));
+        assert_eq!(response.status, 200);
+        let response = handler
+            .handle(TestRequest::get("/internal/docs").with_header(AUTHORIZATION, b"Bearer other"));
+        assert_eq!(response.status, 401);
+        let response = handler.handle(TestRequest {
+            method: method::OPTIONS,
+            path: "/internal/docs",
+            headers: vec![
+                (ORIGIN, b"https://example.com"),
+                (CORS_REQUEST_METHOD, b"GET"),
+            ],
+        });
+        assert_eq!(response.status, 204);
+        let response = handler.handle(TestRequest::get("/"));
+        assert_eq!(response.status, 200);
+        assert_eq!(
+            response.header(CACHE_CONTROL),
+            Some(b"public,max-age=3600,stale-if-error=3600".as_slice())
+        );
+        assert!(
+            Handler::builder()
+                .with_zip(zip(&[("index.html", HTML)]))
+                .with_auth("/", AuthPolicy::new("empty"))
+                .try_build()
+                .is_err()
+        );
+    }
+
+    #[cfg(feature = "auth")]
+    #[test]
+    fn auth_rewrite() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[
+                ("index.html", HTML),
+                ("internal/report.html", b"<p>report</p>"),
+                ("internal/app/index.html", b"<p>app</p>"),
+                ("_redirects", b"/public/* /internal/report 200\n"),
+            ]))
+            .with_auth(
+                "/internal",
+                AuthPolicy::new("internal").with_bearer_token("token"),
+            )
+            .with_spa_fallback(SpaFallback::new("/app", "/internal/app"))
+            .try_build()
+            .unwrap();
+        for path in ["/public/report", "/app/settings"] {
+            let response = handler.handle(TestRequest::get(path).with_header(ACCEPT, b"text/html"));
+            assert_eq!(response.status, 401, "{path}");
+            assert_eq!(response.body, None);
+            assert_eq!(
+                response.header(WWW_AUTHENTICATE),
+                Some(b"Bearer realm=\"internal\"".as_slice())
+            );
+        }
+        let response = handler
+            .handle(TestRequest::get("/public/report").with_header(AUTHORIZATION, b"Bearer token"));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.as_deref(), Some(b"<p>report</p>".as_slice()));
+        let response = handler.handle(
+            TestRequest::get("/app/settings")
+                .with_header(ACCEPT, b"text/html")
+                .with_header(AUTHORIZATION, b"Bearer token"),
+        );
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.as_deref(), Some(b"<p>app</p>".as_slice()));
+    }
+
+    #[cfg(feature = "auth")]
+    #[test]
+    fn auth_error_pages() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[
+                ("index.html", HTML),
+                ("4xx.html", b"<p>client error</p>"),
+                ("internal/docs.html", HTML),
+                ("internal/4xx.html", b"<p>internal error</p>"),
+                ("releases/app.zip", b"zip"),
+                ("releases/4xx.html", b"<p>releases error</p>"),
+            ]))
+            .with_error_page("4xx", "4xx.html")
+            .with_auth(
+                "/internal",
+                AuthPolicy::new("internal").with_bearer_token("token"),
+            )
+            .with_signed_urls("/releases", b"secret".as_slice())
+            .try_build()
+            .unwrap();
+        let response = handler.handle(TestRequest::get("/internal/docs"));
+        assert_eq!(response.status, 401);
+        assert_eq!(
+            response.body.as_deref(),
+            Some(b"<p>client error</p>".as_slice())
+        );
+        assert_eq!(
+            response.header(WWW_AUTHENTICATE),
+            Some(b"Bearer realm=\"internal\"".as_slice())
+        );
+        let response = handler.handle(TestRequest::get("/releases/app.zip"));
+        assert_eq!(response.status, 403);
+        assert_eq!(
+            response.body.as_deref(),
+            Some(b"<p>client error</p>".as_slice())
+        );
+        // the protected pages are served to the requests that passed the checks
+        let response = handler.handle(
+            TestRequest::get("/internal/missing").with_header(AUTHORIZATION, b"Bearer token"),
+        );
+        assert_eq!(response.status, 404);
+        assert_eq!(
+            response.body.as_deref(),
+            Some(b"<p>internal error</p>".as_slice())
+        );
+    }
+
+    #[cfg(feature = "auth")]
+    #[test]
+    fn signed_urls() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[("index.html", HTML), ("releases/app.zip", b"zip")]))
+            .with_signed_urls("/releases", b"secret".as_slice())
+            .with_canonical_redirects()
+            .try_build()
+            .unwrap();
+        let expires = SystemTime::now() + Duration::from_secs(60);
+        let url: &'static str = sign_url(b"secret", "/releases/app.zip", expires).leak();
+        let response = handler.handle(TestRequest::get(url));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.as_deref(), Some(b"zip".as_slice()));
+        assert_eq!(
+            handler.handle(TestRequest::get("/releases/app.zip")).status,
+            403
+        );
+        let expired = sign_url(b"secret", "/releases/app.zip", SystemTime::now()).leak();
+        assert_eq!(handler.handle(TestRequest::get(expired)).status, 403);
+        let other_key = sign_url(b"other", "/releases/app.zip", expires).leak();
+        assert_eq!(handler.handle(TestRequest::get(other_key)).status, 403);
+        // missing entries under the prefix are not revealed without a valid signature
+        let missing = sign_url(b"other", "/releases/missing.zip", expires).leak();
+        assert_eq!(handler.handle(TestRequest::get(missing)).status, 403);
+        // the signature is kept when redirecting to the canonical path
+        let response = handler.handle(TestRequest::get(format!("/{url}").leak()));
+        assert_eq!(response.status, 308);
+        assert_eq!(response.header(LOCATION), Some(url.as_bytes()));
+        assert_eq!(handler.handle(TestRequest::get("/")).status, 200);
+    }
+
+    #[cfg(feature = "auth")]
+    #[test]
+    fn signed_urls_rewrite() {
+        let handler = Handler::builder()
+            .with_zip(zip(&[
+                ("index.html", HTML),
+                ("releases/app.zip", b"zip"),
+                ("_redirects", b"/latest /releases/app.zip 200\n"),
+            ]))
+            .with_signed_urls("/releases", b"secret".as_slice())
+            .try_build()
+            .unwrap();
+        assert_eq!(handler.handle(TestRequest::get("/latest")).status, 403);
+        let expires = SystemTime::now() + Duration::from_secs(60);
+        let signed = sign_url(b"secret", "/releases/app.zip", expires);
+        let query = &signed[signed.find('?').unwrap()..];
+        let response = handler.handle(TestRequest::get(format!("/latest{query}").leak()));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.as_deref(), Some(b"zip".as_slice()));
+    }
+
+    #[test]
+    fn security_headers() {
+        let files: &[(&str, &[u8])] = &[("index.html", HTML), ("a.png", b"png")];
+        let handler = Handler::builder().with_zip(zip(files)).try_build().unwrap();
+        let page = handler.handle(TestRequest::get("/"));
+        let image = handler.handle(TestRequest::get("/a.png"));
+        for key in [
+            CSP,
+            COOP,
+            X_FRAME_OPTIONS,
+            HSTS,
+            REFERRER_POLICY,
+            PERMISSIONS_POLICY,
+        ] {
+            assert!(page.header(key).is_some());
+            assert_eq!(image.header(key), None);
+        }
+        for key in [ALLOW, X_CONTENT_TYPE_OPTIONS, CORP] {
+            assert!(page.header(key).is_some());
+            assert!(image.header(key).is_some());
+        }
+        let handler = Handler::builder()
+            .with_zip(zip(files))
+            .with_security_headers(
+                SecurityHeaders::strict()
+                    .with_document_header(REFERRER_POLICY, "same-origin")
+                    .without_header(X_FRAME_OPTIONS),
+            )
+            .try_build()
+            .unwrap();
+        let page = handler.handle(TestRequest::get("/"));
+        let image = handler.handle(TestRequest::get("/a.png"));
+        assert_eq!(
+            page.header(REFERRER_POLICY),
+            Some(b"same-origin".as_slice())
+        );
+        assert_eq!(page.header(X_FRAME_OPTIONS), None);
+        assert!(image.header(HSTS).is_some());
+        assert_eq!(image.header(CSP), None);
+        let handler = Handler::builder()
+            .with_zip(zip(files))
+            .with_security_headers(SecurityHeaders::relaxed().without_hsts())
+            .try_build()
+            .unwrap();
+        assert_eq!(handler.handle(TestRequest::get("/")).header(HSTS), None);
+    }
+
+    #[cfg(feature = "swappable")]
+    #[test]
+    fn swappable() {
+        let handler = SwappableHandler::new(
+            Handler::builder()
+                .with_zip(zip(&[("index.html", HTML)]))
+                .try_build()
+                .unwrap(),
+        );
+        let previous = handler.current();
+        assert_eq!(handler.handle(TestRequest::get("/")).status, 200);
+        assert_eq!(handler.handle(TestRequest::get("/a")).status, 404);
+        handler
+            .replace(Handler::builder().with_zip(zip(&[("index.html", HTML), ("a.html", HTML)])))
+            .unwrap();
+        assert_eq!(handler.handle(TestRequest::get("/a")).status, 200);
+        assert_eq!(previous.handle(TestRequest::get("/a")).status, 404);
+        // the compressed content of the unchanged entry is reused
+        let brotli = |handler: &Handler| handler.entry("/").unwrap().encoded[0].content.as_ptr();
+        assert_eq!(brotli(&handler.current()), brotli(&previous));
+        // the current handler is kept when the new one can't be built
+        assert!(
+            handler
+                .replace(Handler::builder().with_zip(b"not a zip".as_slice()))
+                .is_err()
+        );
+        assert_eq!(handler.handle(TestRequest::get("/a")).status, 200);
+    }
+
+    #[test]
+    fn directory() {
+        let brotli = crate::compression::compress_brotli(HTML, HTML.len(), Default::default());
+        let files: &[(&str, &[u8])] = &[
+            ("site/_redirects", b"/old /a 301"),
+            ("site/index.html", HTML),
+            ("site/index.html.br", &brotli),
+            ("site/a/index.html", HTML),
+            ("site/style.css", b"p{}"),
+            ("site/.git/config", b"[core]"),
+        ];
+        let root = std::env::temp_dir().join(format!("zip_static_handler_{}", std::process::id()));
+        for (name, content) in files {
+            let path = root.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        let from_directory = Handler::builder()
+            .with_zip_prefix("site/")
+            .with_directory(&root)
+            .try_build();
+        std::fs::remove_dir_all(&root).unwrap();
+        let from_directory = from_directory.unwrap();
+        let from_zip = Handler::builder()
+            .with_zip_prefix("site/")
+            .with_zip(zip(files))
+            .try_build()
+            .unwrap();
+        for path in [
+            "/",
+            "/a",
+            "/style.css",
+            "/old",
+            "/.git/config",
+            "/index.html.br",
+        ] {
+            let expected = from_zip.handle(TestRequest::get(path));
+            let response = from_directory.handle(TestRequest::get(path));
+            assert_eq!(response.status, expected.status, "{path}");
+            assert_eq!(response.header(ETAG), expected.header(ETAG), "{path}");
+            assert_eq!(response.body, expected.body, "{path}");
+        }
+        assert!(
+            from_directory
+                .handle(TestRequest::get("/style.css"))
+                .header(LAST_MODIFIED)
+                .is_some()
+        );
+        // the pre-compressed sidecar is served as is
+        let response =
+            from_directory.handle(TestRequest::get("/").with_header(ACCEPT_ENCODING, b"br"));
+        assert_eq!(response.body.as_deref(), Some(brotli.as_slice()));
+        assert!(
+            Handler::builder()
+                .with_directory(root.join("missing"))
+                .try_build()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn tar() {
+        let brotli = crate::compression::compress_brotli(HTML, HTML.len(), Default::default());
+        let files: &[(&str, &[u8])] = &[
+            ("./_redirects", b"/old /a 301"),
+            ("./index.html", HTML),
+            ("./index.html.br", &brotli),
+            ("./a/index.html", HTML),
+            ("./style.css", b"p{}"),
+        ];
+        let mut tar = vec![];
+        for (name, content) in files {
+            tar.extend_from_slice(&crate::source::tests::tar_header(name, content.len(), b'0'));
+            tar.extend_from_slice(content);
+            tar.resize(tar.len().div_ceil(512) * 512, 0);
+        }
+        tar.resize(tar.len() + 1024, 0);
+        let gzip = |bytes: &[u8]| {
+            let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+            std::io::Write::write_all(&mut encoder, bytes).unwrap();
+            encoder.finish().unwrap()
+        };
+        let tar_gz = gzip(&tar);
+        // concatenated members (pigz, cat), padded with zeros
+        let mut multi_member = gzip(&tar[..1000]);
+        multi_member.extend_from_slice(&gzip(&tar[1000..]));
+        multi_member.resize(multi_member.len() + 512, 0);
+        let from_zip = Handler::builder().with_zip(zip(files)).try_build().unwrap();
+        for from_tar in [
+            Handler::builder().with_tar(tar).try_build().unwrap(),
+            Handler::builder()
+                .with_tar(tar_gz.clone())
+                .try_build()
+                .unwrap(),
+            Handler::builder()
+                .with_tar(multi_member)
+                .try_build()
+                .unwrap(),
+        ] {
+            for path in ["/", "/a", "/style.css", "/old", "/index.html.br"] {
+                let expected = from_zip.handle(TestRequest::get(path));
+                let response = from_tar.handle(TestRequest::get(path));
+                assert_eq!(response.status, expected.status, "{path}");
+                assert_eq!(response.header(ETAG), expected.header(ETAG), "{path}");
+                assert_eq!(response.body, expected.body, "{path}");
+            }
+            let response =
+                from_tar.handle(TestRequest::get("/").with_header(ACCEPT_ENCODING, b"br"));
+            assert_eq!(response.body.as_deref(), Some(brotli.as_slice()));
+        }
+        assert!(
+            Handler::builder()
+                .with_tar(b"not a tar".as_slice())
+                .try_build()
+                .is_err()
+        );
+        let mut trailing_garbage = tar_gz.clone();
+        trailing_garbage.extend_from_slice(b"garbage");
+        for invalid in [&tar_gz[..tar_gz.len() - 4], &trailing_garbage] {
+            assert!(Handler::builder().with_tar(invalid).try_build().is_err());
+        }
+    }
+
+    #[test]
+    fn layers() {
+        let theme = zip(&[
+            ("theme-main/index.html", b"theme index".as_slice()),
+            ("theme-main/style.css", b"p{}"),
+            ("theme-main/about.html", b"about"),
+            ("theme-main/legacy/a.html", b"a"),
+            ("theme-main/legacy/b.html", b"b"),
+            ("theme-main/fonts/a.woff2", b"font"),
+            ("theme-main/fonts/b.woff2", b"font"),
+        ]);
+        let tenant = zip(&[
+            ("index.html", b"tenant index".as_slice()),
+            ("docs/index.html", b"docs"),
+            ("legacy/.wh.a.html", b""),
+            (".wh.fonts", b""),
+        ]);
+        let overrides = zip(&[
+            ("docs/.wh..wh..opq", b"".as_slice()),
+            ("docs/faq.html", b"faq"),
+            ("about.html", b"new about"),
+        ]);
+        let handler = Handler::builder()
+            .with_layers(
+                Layers::new()
+                    .with_zip("theme-main/", theme)
+                    .with_zip("", tenant)
+                    .with_zip("", overrides),
+            )
+            .try_build()
+            .unwrap();
+        let body = |path| handler.handle(TestRequest::get(path)).body;
+        assert_eq!(body("/").as_deref(), Some(b"tenant index".as_slice()));
+        assert_eq!(body("/about").as_deref(), Some(b"new about".as_slice()));
+        assert_eq!(body("/style.css").as_deref(), Some(b"p{}".as_slice()));
+        assert_eq!(body("/legacy/b").as_deref(), Some(b"b".as_slice()));
+        assert_eq!(body("/docs/faq").as_deref(), Some(b"faq".as_slice()));
+        for deleted in [
+            "/legacy/a",
+            "/fonts/a.woff2",
+            "/fonts/b.woff2",
+            "/docs",
+            "/.wh.fonts",
+        ] {
+            assert_eq!(handler.handle(TestRequest::get(deleted)).status, 404);
+        }
+        assert_eq!(handler.layer("/style.css"), Some(0));
+        assert_eq!(handler.layer("/legacy/b"), Some(0));
+        assert_eq!(handler.layer("/legacy/b/"), Some(0));
+        assert_eq!(handler.layer("/"), Some(1));
+        assert_eq!(handler.layer("/about"), Some(2));
+        assert_eq!(handler.layer("/docs/faq"), Some(2));
+        assert_eq!(handler.layer("/missing"), None);
+        let handler = Handler::builder()
+            .with_zip(zip(&[("index.html", HTML)]))
+            .try_build()
+            .unwrap();
+        assert_eq!(handler.layer("/"), None);
+    }
+
+    #[cfg(feature = "csp")]
+    #[test]
+    fn csp_hashes() {
+        let page: &[u8] = b"<html><head><style>p{color:red}</style></head>\
+            <body><p style=\"margin:0\">a</p><script>main()</script></body></html>";
+        let handler = Handler::builder()
+            .with_zip(zip(&[("index.html", page), ("style.css", b"p{}")]))
+            .with_security_headers(
+                SecurityHeaders::default().with_resource_header(CSP, "style-src 'unsafe-inline'"),
+            )
+            .with_csp_hashes()
+            .try_build()
+            .unwrap();
+        let response = handler.handle(TestRequest::get("/"));
+        assert_eq!(response.status, 200);
+        let csp = String::from_utf8(response.header(CSP).unwrap().to_vec()).unwrap();
+        // echo -n "main()" | openssl sha256 -binary | openssl base64
+        assert!(csp.contains(
+            "script-src-elem 'self' 'sha256-6hoPkQIwXgLxKsWKzAGrY2XKW7XlbiW5FxOYCLnJMsE='"
+        ));
+        // the page has a style attribute, and there's no style-src-attr directive
+        assert!(csp.contains("style-src 'self' 'unsafe-inline'"));
+        let response = handler.handle(TestRequest::get("/style.css"));
+        assert_eq!(
+            response.header(CSP),
+            Some(b"style-src 'unsafe-inline'".as_slice())
+        );
+    }
+
+    #[test]
+    fn hidden_paths() {
+        let files: &[(&str, &[u8])] = &[
+            ("index.html", HTML),
+            (".well-known/security.txt", b"Contact: a@b.c"),
+            (".well-known/apple-app-site-association", b"{}"),
+            (".well-known/acme-challenge/token", b"token.key"),
+            (".git/config", b"[core]"),
+            ("a/.settings.json", b"{}"),
+        ];
+        let handler = Handler::builder().with_zip(zip(files)).try_build().unwrap();
+        let response = handler.handle(TestRequest::get("/.well-known/security.txt"));
+        assert_eq!(response.status, 200);
+        assert_eq!(
+            response.header(CONTENT_TYPE),
+            Some(b"text/plain".as_slice())
+        );
+        let response = handler.handle(TestRequest::get("/.well-known/apple-app-site-association"));
+        assert_eq!(response.status, 200);
+        assert_eq!(
+            response.header(CONTENT_TYPE),
+            Some(b"application/json".as_slice())
+        );
+        let response = handler.handle(TestRequest::get("/.well-known/acme-challenge/token"));
+        assert_eq!(response.status, 200);
+        assert_eq!(
+            response.header(CONTENT_TYPE),
+            Some(b"text/plain".as_slice())
+        );
+        assert_eq!(handler.handle(TestRequest::get("/.git/config")).status, 404);
+        assert_eq!(
+            handler.handle(TestRequest::get("/a/.settings.json")).status,
+            404
+        );
+        let handler = Handler::builder()
+            .with_zip(zip(files))
+            .with_hidden_paths(
+                HiddenPaths::default()
+                    .allow("a")
+                    .deny(".well-known/acme-challenge"),
+            )
+            .try_build()
+            .unwrap();
+        assert_eq!(
+            handler.handle(TestRequest::get("/a/.settings.json")).status,
+            200
+        );
+        assert_eq!(
+            handler
+                .handle(TestRequest::get("/.well-known/security.txt"))
+                .status,
+            200
+        );
+        assert_eq!(
+            handler
+                .handle(TestRequest::get("/.well-known/acme-challenge/token"))
+                .status,
+            404
+        );
+        assert_eq!(handler.handle(TestRequest::get("/.git/config")).status, 404);
+    }
+
     fn download(url: &str) -> Vec<u8> {
         debug!(url = url);
         let response = Client::default()
             .get(url)
             .send()
-            .expect(&format!("failed to download {url}"));
+            .unwrap_or_else(|_| panic!("failed to download {url}"));
         if !response.status().is_success() {
             panic!("failed to download {url} ({})", response.status().as_str());
         }
@@ -264,7 +2303,7 @@ mod tests {
             COMMIT_HASH,
         ));
         let handler = Handler::builder()
-            .with_zip_prefix(&format!("about.programingjd.me-{COMMIT_HASH}/"))
+            .with_zip_prefix(format!("about.programingjd.me-{COMMIT_HASH}/"))
             .with_zip(zip)
             .try_build();
         assert!(handler.is_ok());
@@ -276,18 +2315,18 @@ mod tests {
             favicon
                 .headers
                 .iter()
-                .find_map(|ref line| if line.key == CONTENT_TYPE {
+                .find_map(|line| if line.key == CONTENT_TYPE {
                     Some(line.value.as_ref())
                 } else {
                     None
                 }),
             Some(b"image/png".as_slice())
         );
-        assert!(handler.paths.get("/.idea/modules.xml").is_none());
-        assert!(handler.paths.get("/").is_some());
+        assert!(!handler.paths.contains_key("/.idea/modules.xml"));
+        assert!(handler.paths.contains_key("/"));
         assert!(handler.paths.get("/").unwrap().etag.is_some());
-        assert!(handler.paths.get("/index.html").is_none());
-        assert!(handler.paths.get("/about").is_some());
+        assert!(!handler.paths.contains_key("/index.html"));
+        assert!(handler.paths.contains_key("/about"));
         assert!(handler.paths.get("/about").unwrap().content.is_none());
         assert!(handler.paths.get("/about").unwrap().etag.is_none());
         assert!(
@@ -321,7 +2360,7 @@ mod tests {
                 .as_ref(),
             b"."
         );
-        assert!(handler.paths.get("/profile.jpg").is_some());
+        assert!(handler.paths.contains_key("/profile.jpg"));
         assert!(handler.paths.get("/profile.jpg").unwrap().content.is_none());
         assert!(handler.paths.get("/profile.jpg").unwrap().etag.is_some());
         assert!(
@@ -346,7 +2385,7 @@ mod tests {
                 .as_ref(),
             b"profile_512.jpg"
         );
-        assert!(handler.paths.get("/about/").is_some());
+        assert!(handler.paths.contains_key("/about/"));
         assert!(handler.paths.get("/about/").unwrap().content.is_none());
         assert!(handler.paths.get("/about/").unwrap().etag.is_none());
         assert!(
@@ -381,7 +2420,7 @@ mod tests {
             COMMIT_HASH,
         ));
         let handler = Handler::builder()
-            .with_zip_prefix(&format!("about.programingjd.me-{COMMIT_HASH}/"))
+            .with_zip_prefix(format!("about.programingjd.me-{COMMIT_HASH}/"))
             .with_zip(zip)
             .with_root_prefix("test/")
             .try_build();
@@ -394,19 +2433,19 @@ mod tests {
             favicon
                 .headers
                 .iter()
-                .find_map(|ref line| if line.key == CONTENT_TYPE {
+                .find_map(|line| if line.key == CONTENT_TYPE {
                     Some(line.value.as_ref())
                 } else {
                     None
                 }),
             Some(b"image/png".as_slice())
         );
-        assert!(handler.paths.get("/.idea/modules.xml").is_none());
-        assert!(handler.paths.get("/").is_none());
-        assert!(handler.paths.get("/index.html").is_none());
-        assert!(handler.paths.get("/test/.idea/modules.xml").is_none());
-        assert!(handler.paths.get("/test/index.html").is_none());
-        assert!(handler.paths.get("/test/").is_some());
+        assert!(!handler.paths.contains_key("/.idea/modules.xml"));
+        assert!(!handler.paths.contains_key("/"));
+        assert!(!handler.paths.contains_key("/index.html"));
+        assert!(!handler.paths.contains_key("/test/.idea/modules.xml"));
+        assert!(!handler.paths.contains_key("/test/index.html"));
+        assert!(handler.paths.contains_key("/test/"));
         assert!(handler.paths.get("/test/").unwrap().content.is_none());
         assert!(handler.paths.get("/test/").unwrap().etag.is_none());
         assert!(
@@ -431,9 +2470,9 @@ mod tests {
                 .as_ref(),
             b"/test"
         );
-        assert!(handler.paths.get("/test").is_some());
+        assert!(handler.paths.contains_key("/test"));
         assert!(handler.paths.get("/test").unwrap().etag.is_some());
-        assert!(handler.paths.get("/test/about").is_some());
+        assert!(handler.paths.contains_key("/test/about"));
         assert!(handler.paths.get("/test/about").unwrap().content.is_none());
         assert!(handler.paths.get("/test/about").unwrap().etag.is_none());
         assert!(
@@ -458,7 +2497,7 @@ mod tests {
                 .as_ref(),
             b"."
         );
-        assert!(handler.paths.get("/test/profile.jpg").is_some());
+        assert!(handler.paths.contains_key("/test/profile.jpg"));
         assert!(
             handler
                 .paths
@@ -497,7 +2536,7 @@ mod tests {
                 .as_ref(),
             b"profile_512.jpg"
         );
-        assert!(handler.paths.get("/test/about/").is_some());
+        assert!(handler.paths.contains_key("/test/about/"));
         assert!(handler.paths.get("/test/about/").unwrap().content.is_none());
         assert!(handler.paths.get("/test/about/").unwrap().etag.is_none());
         assert!(
@@ -0,0 +1,293 @@
+use crate::errors::Result;
+use crate::http::OwnedOrStatic;
+use crate::http::headers::{
+    ACCEPT_RANGES, ALLOW, CACHE_CONTROL, COEP, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE,
+    CONTENT_TYPE, COOP, CORP, CORS, CORS_ALLOW_CREDENTIALS, CORS_ALLOW_HEADERS, CORS_ALLOW_METHODS,
+    CORS_MAX_AGE, CSP, ETAG, HSTS, LAST_MODIFIED, LOCATION, Line, PERMISSIONS_POLICY,
+    REFERRER_POLICY, SERVICE_WORKER_ALLOWED, VARY, WWW_AUTHENTICATE, X_CONTENT_TYPE_OPTIONS,
+    X_FRAME_OPTIONS, X_XSS_PROTECTION,
+};
+
+// Headers that depend on the representation, and that are managed by the handler.
+const MANAGED_HEADERS: [&[u8]; 7] = [
+    ACCEPT_RANGES,
+    CONTENT_ENCODING,
+    CONTENT_LENGTH,
+    CONTENT_RANGE,
+    ETAG,
+    LAST_MODIFIED,
+    VARY,
+];
+
+// Response headers with a static name, that the handler looks up by key.
+const STATIC_HEADERS: [&[u8]; 21] = [
+    ALLOW,
+    CACHE_CONTROL,
+    COEP,
+    CONTENT_TYPE,
+    COOP,
+    CORP,
+    CORS,
+    CORS_ALLOW_CREDENTIALS,
+    CORS_ALLOW_HEADERS,
+    CORS_ALLOW_METHODS,
+    CORS_MAX_AGE,
+    CSP,
+    HSTS,
+    LOCATION,
+    PERMISSIONS_POLICY,
+    REFERRER_POLICY,
+    SERVICE_WORKER_ALLOWED,
+    WWW_AUTHENTICATE,
+    X_CONTENT_TYPE_OPTIONS,
+    X_FRAME_OPTIONS,
+    X_XSS_PROTECTION,
+];
+
+enum Action {
+    // lowercase header name and value
+    Set(Vec<u8>, String),
+    Remove(Vec<u8>),
+}
+
+struct Rule {
+    // path, with * wildcards
+    pattern: String,
+    actions: Vec<Action>,
+}
+
+// Rules from a `_headers` file.
+#[derive(Default)]
+pub(crate) struct HeaderRules(Vec<Rule>);
+
+impl HeaderRules {
+    // Parses the content of a `_headers` file: a path (or a glob with * wildcards) on its own
+    // line, followed by indented `Name: value` lines to set a header or `! Name` lines to remove
+    // one. Blank lines and lines starting with # are ignored.
+    // Values with control characters (other than tabs) are rejected.
+    pub(crate) fn parse(content: &str, path_prefix: &str) -> Result<Self> {
+        let mut rules: Vec<Rule> = vec![];
+        for (i, line) in content.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let invalid = || format!("invalid _headers line {}: {trimmed}", i + 1);
+            if !line.starts_with(char::is_whitespace) {
+                if !trimmed.starts_with('/') {
+                    return Err(invalid().into());
+                }
+                rules.push(Rule {
+                    pattern: format!("{path_prefix}{trimmed}"),
+                    actions: vec![],
+                });
+                continue;
+            }
+            let rule = rules.last_mut().ok_or_else(invalid)?;
+            let action = match trimmed.strip_prefix('!') {
+                Some(name) => Action::Remove(header_name(name.trim()).ok_or_else(invalid)?),
+                None => {
+                    let (name, value) = trimmed.split_once(':').ok_or_else(invalid)?;
+                    let name = header_name(name.trim()).ok_or_else(invalid)?;
+                    let value = value.trim();
+                    if value.bytes().any(|b| (b < 0x20 && b != b'\t') || b == 0x7f) {
+                        return Err(invalid().into());
+                    }
+                    Action::Set(name, value.to_string())
+                }
+            };
+            if let Action::Set(name, _) | Action::Remove(name) = &action
+                && MANAGED_HEADERS.contains(&name.as_slice())
+            {
+                return Err(format!(
+                    "header can't be changed in _headers (line {}): {}",
+                    i + 1,
+                    String::from_utf8_lossy(name)
+                )
+                .into());
+            }
+            rule.actions.push(action);
+        }
+        Ok(Self(rules))
+    }
+
+    // Applies the rules matching the route to the headers of its entry, in order.
+    // A header set by a rule replaces the header from the header selector, and the values
+    // set by several rules are combined.
+    pub(crate) fn apply(&self, route: &str, headers: &mut Vec<Line>) {
+        let mut set: Vec<&[u8]> = vec![];
+        for rule in self.0.iter().filter(|it| glob_matches(&it.pattern, route)) {
+            for action in &rule.actions {
+                match action {
+                    Action::Remove(name) => {
+                        headers.retain(|it| it.name() != name.as_slice());
+                        set.retain(|it| it != name);
+                    }
+                    Action::Set(name, value) if set.contains(&name.as_slice()) => {
+                        if let Some(line) =
+                            headers.iter_mut().find(|it| it.name() == name.as_slice())
+                        {
+                            let mut combined = line.value.as_ref().to_vec();
+                            combined.extend_from_slice(b", ");
+                            combined.extend_from_slice(value.as_bytes());
+                            line.value = OwnedOrStatic::Owned(combined);
+                        }
+                    }
+                    Action::Set(name, value) => {
+                        headers.retain(|it| it.name() != name.as_slice());
+                        let value = value.as_bytes().to_vec();
+                        headers.push(
+                            match STATIC_HEADERS.iter().find(|&&it| it == name.as_slice()) {
+                                Some(key) => Line::with_owned_value(key, value),
+                                None => Line::with_custom_key(name.clone(), value),
+                            },
+                        );
+                        set.push(name);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Lowercase header name, if it's a valid token.
+fn header_name(name: &str) -> Option<Vec<u8>> {
+    let valid = !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+    if !valid {
+        return None;
+    }
+    Some(name.to_ascii_lowercase().into_bytes())
+}
+
+// Whether the path matches the pattern, where * matches any sequence of characters.
+fn glob_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, path) = (pattern.as_bytes(), path.as_bytes());
+    let (mut p, mut s) = (0, 0);
+    // position of the last * in the pattern, and of the path when it was reached
+    let mut backtrack = None;
+    while s < path.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            backtrack = Some((p, s));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == path[s] {
+            p += 1;
+            s += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            s = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&b| b == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::headers::{CACHE_CONTROL, CSP, X_FRAME_OPTIONS};
+
+    const HEADERS: &str = "\
+# all pages
+/*
+  X-Frame-Options: SAMEORIGIN
+  Link: </style.css>; rel=preload; as=style
+
+/admin/*
+  ! X-Frame-Options
+  Content-Security-Policy: default-src 'self'
+
+/*.js
+  Cache-Control: public, max-age=31536000, immutable
+  Link: </app.js>; rel=modulepreload
+";
+
+    fn header<'a>(headers: &'a [Line], key: &[u8]) -> Option<&'a [u8]> {
+        headers
+            .iter()
+            .find(|it| it.name() == key)
+            .map(|it| it.value.as_ref())
+    }
+
+    fn headers() -> Vec<Line> {
+        vec![
+            Line::with_slice_value(X_FRAME_OPTIONS, b"DENY"),
+            Line::with_slice_value(CACHE_CONTROL, b"no-cache"),
+        ]
+    }
+
+    #[test]
+    fn glob() {
+        assert!(glob_matches("/*", "/"));
+        assert!(glob_matches("/*", "/a/b"));
+        assert!(glob_matches("/a", "/a"));
+        assert!(!glob_matches("/a", "/a/b"));
+        assert!(glob_matches("/*.js", "/a/b.js"));
+        assert!(!glob_matches("/*.js", "/a/b.json"));
+        assert!(glob_matches("/a/*/c", "/a/b/c"));
+        assert!(glob_matches("/a*b*c", "/abbbc"));
+        assert!(!glob_matches("/a*b*c", "/abbbd"));
+    }
+
+    #[test]
+    fn apply() {
+        let rules = HeaderRules::parse(HEADERS, "").unwrap();
+        let mut page = headers();
+        rules.apply("/about", &mut page);
+        assert_eq!(
+            header(&page, X_FRAME_OPTIONS),
+            Some(b"SAMEORIGIN".as_slice())
+        );
+        assert_eq!(header(&page, CACHE_CONTROL), Some(b"no-cache".as_slice()));
+        let mut admin = headers();
+        rules.apply("/admin/users", &mut admin);
+        assert_eq!(header(&admin, X_FRAME_OPTIONS), None);
+        assert_eq!(header(&admin, CSP), Some(b"default-src 'self'".as_slice()));
+        let mut script = headers();
+        rules.apply("/app.js", &mut script);
+        assert_eq!(
+            header(&script, CACHE_CONTROL),
+            Some(b"public, max-age=31536000, immutable".as_slice())
+        );
+        assert_eq!(
+            header(&script, b"link"),
+            Some(b"</style.css>; rel=preload; as=style, </app.js>; rel=modulepreload".as_slice())
+        );
+        // known names keep their static key, the others are only available with `name`
+        assert!(script.iter().any(|it| it.key == CACHE_CONTROL));
+        assert!(!script.iter().any(|it| it.key == b"link"));
+    }
+
+    #[test]
+    fn invalid() {
+        for invalid in [
+            "  X-Frame-Options: DENY",
+            "a\n  X-Frame-Options: DENY",
+            "/a\n  X-Frame-Options DENY",
+            "/a\n  Bad Name: value",
+            "/a\n  Content-Length: 0",
+            "/a\n  ! ETag",
+            "/a\n  X-Frame-Options: DENY\rSet-Cookie: a=b",
+            "/a\n  X-Frame-Options: DENY\0",
+        ] {
+            assert!(HeaderRules::parse(invalid, "").is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn prefix() {
+        let rules = HeaderRules::parse("/a\n  X-Frame-Options: SAMEORIGIN", "/site").unwrap();
+        let mut headers = headers();
+        rules.apply("/a", &mut headers);
+        assert_eq!(header(&headers, X_FRAME_OPTIONS), Some(b"DENY".as_slice()));
+        rules.apply("/site/a", &mut headers);
+        assert_eq!(
+            header(&headers, X_FRAME_OPTIONS),
+            Some(b"SAMEORIGIN".as_slice())
+        );
+    }
+}
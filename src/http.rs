@@ -25,11 +25,21 @@ impl AsRef<[u8]> for OwnedOrStatic {
 pub mod headers {
     use crate::http::OwnedOrStatic;
 
+    pub const ACCEPT: &[u8] = b"accept";
+    pub const ACCEPT_ENCODING: &[u8] = b"accept-encoding";
+    pub const ACCEPT_RANGES: &[u8] = b"accept-ranges";
     pub const ALLOW: &[u8] = b"allow";
+    pub const AUTHORIZATION: &[u8] = b"authorization";
     pub const CORS: &[u8] = b"access-control-allow-origin";
+    pub const CORS_ALLOW_CREDENTIALS: &[u8] = b"access-control-allow-credentials";
+    pub const CORS_ALLOW_HEADERS: &[u8] = b"access-control-allow-headers";
+    pub const CORS_ALLOW_METHODS: &[u8] = b"access-control-allow-methods";
+    pub const CORS_MAX_AGE: &[u8] = b"access-control-max-age";
+    pub const CORS_REQUEST_METHOD: &[u8] = b"access-control-request-method";
     pub const CACHE_CONTROL: &[u8] = b"cache-control";
     pub const CONTENT_ENCODING: &[u8] = b"content-encoding";
     pub const CONTENT_LENGTH: &[u8] = b"content-length";
+    pub const CONTENT_RANGE: &[u8] = b"content-range";
     pub const CONTENT_TYPE: &[u8] = b"content-type";
     pub const COEP: &[u8] = b"cross-origin-embedder-policy";
     pub const COOP: &[u8] = b"cross-origin-opener-policy";
@@ -37,10 +47,19 @@ pub mod headers {
     pub const CSP: &[u8] = b"content-security-policy";
     pub const ETAG: &[u8] = b"etag";
     pub const IF_MATCH: &[u8] = b"if-match";
+    pub const IF_MODIFIED_SINCE: &[u8] = b"if-modified-since";
     pub const IF_NONE_MATCH: &[u8] = b"if-none-match";
+    pub const IF_RANGE: &[u8] = b"if-range";
+    pub const IF_UNMODIFIED_SINCE: &[u8] = b"if-unmodified-since";
+    pub const LAST_MODIFIED: &[u8] = b"last-modified";
     pub const LOCATION: &[u8] = b"location";
+    pub const ORIGIN: &[u8] = b"origin";
+    pub const PERMISSIONS_POLICY: &[u8] = b"permissions-policy";
     pub const HSTS: &[u8] = b"strict-transport-security";
+    pub const RANGE: &[u8] = b"range";
+    pub const REFERRER_POLICY: &[u8] = b"referrer-policy";
     pub const SERVICE_WORKER_ALLOWED: &[u8] = b"service-worker-allowed";
+    pub const VARY: &[u8] = b"vary";
     pub const WWW_AUTHENTICATE: &[u8] = b"www-authenticate";
     pub const X_CONTENT_TYPE_OPTIONS: &[u8] = b"x-content-type-options";
     pub const X_FRAME_OPTIONS: &[u8] = b"x-frame-options";
@@ -52,8 +71,11 @@ pub mod headers {
 
     #[derive(Debug)]
     pub struct Line {
+        /// Lowercase header name, empty for the names that are only known at runtime (see `name`).
         pub key: &'static [u8],
         pub value: OwnedOrStatic,
+        // lowercase name of a header from a `_headers` file that isn't one of the static names
+        pub(crate) custom_key: Option<Vec<u8>>,
     }
 
     impl Line {
@@ -64,20 +86,34 @@ pub mod headers {
             Self {
                 key,
                 value: OwnedOrStatic::Static(value.as_slice()),
+                custom_key: None,
             }
         }
         pub fn with_slice_value(key: &'static [u8], value: &'static [u8]) -> Self {
             Self {
                 key,
                 value: OwnedOrStatic::Static(value),
+                custom_key: None,
             }
         }
         pub fn with_owned_value(key: &'static [u8], value: Vec<u8>) -> Self {
             Self {
                 key,
                 value: OwnedOrStatic::Owned(value),
+                custom_key: None,
             }
         }
+        pub(crate) fn with_custom_key(key: Vec<u8>, value: Vec<u8>) -> Self {
+            Self {
+                key: b"",
+                value: OwnedOrStatic::Owned(value),
+                custom_key: Some(key),
+            }
+        }
+        /// Lowercase header name.
+        pub fn name(&self) -> &[u8] {
+            self.custom_key.as_deref().unwrap_or(self.key)
+        }
     }
 
     impl AsRef<Line> for Line {
@@ -91,6 +127,7 @@ pub mod headers {
             Self {
                 key: self.key,
                 value: self.value.clone(),
+                custom_key: self.custom_key.clone(),
             }
         }
     }
@@ -100,6 +137,7 @@ pub mod headers {
             Self {
                 key: value.0,
                 value: OwnedOrStatic::Static(value.1),
+                custom_key: None,
             }
         }
     }
@@ -109,15 +147,20 @@ pub mod headers {
             Self {
                 key: value.0,
                 value: OwnedOrStatic::Static(value.1.as_slice()),
+                custom_key: None,
             }
         }
     }
 }
 
 pub mod response {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
     pub enum StatusCode {
         OK,
         NoContent,
+        PartialContent,
+        MovedPermanently,
+        Found,
         NotModified,
         TemporaryRedirect,
         PermanentRedirect,
@@ -129,6 +172,7 @@ pub mod response {
         RequestTimeout,
         PreconditionFailed,
         RequestTooLarge,
+        RangeNotSatisfiable,
         TooManyRequests,
         InternalServerError,
         NotImplemented,
@@ -142,6 +186,9 @@ pub mod response {
             match value {
                 StatusCode::OK => 200,
                 StatusCode::NoContent => 204,
+                StatusCode::PartialContent => 206,
+                StatusCode::MovedPermanently => 301,
+                StatusCode::Found => 302,
                 StatusCode::NotModified => 304,
                 StatusCode::TemporaryRedirect => 307,
                 StatusCode::PermanentRedirect => 308,
@@ -153,6 +200,7 @@ pub mod response {
                 StatusCode::RequestTimeout => 408,
                 StatusCode::PreconditionFailed => 412,
                 StatusCode::RequestTooLarge => 413,
+                StatusCode::RangeNotSatisfiable => 416,
                 StatusCode::TooManyRequests => 429,
                 StatusCode::InternalServerError => 500,
                 StatusCode::NotImplemented => 501,
@@ -167,7 +215,7 @@ pub mod response {
 pub mod method {
     pub const HEAD: &[u8] = b"HEAD";
     pub const GET: &[u8] = b"GET";
-    // pub const OPTIONS: &[u8] = b"OPTIONS";
+    pub const OPTIONS: &[u8] = b"OPTIONS";
 }
 
 pub mod request {
@@ -178,6 +226,11 @@ pub mod request {
     pub trait Request<R> {
         fn method(&self) -> &[u8];
         fn path(&self) -> &[u8];
+        /// Query of the request target (without the `?`).
+        /// Defaults to the query in the path, for requests whose path includes it.
+        fn query(&self) -> &[u8] {
+            crate::normalize::query(self.path())
+        }
         fn first_header_value(&self, key: &'static [u8]) -> Option<&[u8]>;
         fn response<'b>(
             self,
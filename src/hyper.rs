@@ -2,6 +2,8 @@ use crate::handler::Handler;
 use crate::http::headers::Line;
 use crate::http::request::Request;
 use crate::http::response::StatusCode;
+#[cfg(feature = "swappable")]
+use crate::swappable::SwappableHandler;
 use http_body_util::{Either, Empty, Full};
 use hyper::body::Bytes;
 use hyper::http::{HeaderName, HeaderValue};
@@ -16,6 +18,13 @@ impl Handler {
     }
 }
 
+#[cfg(feature = "swappable")]
+impl SwappableHandler {
+    pub fn handle_hyper_request(&self, request: HyperRequest) -> HyperResponse {
+        self.load().handle_hyper_request(request)
+    }
+}
+
 struct RequestAdapter {
     inner: HyperRequest,
 }
@@ -29,6 +38,10 @@ impl Request<HyperResponse> for RequestAdapter {
         self.inner.uri().path().as_bytes()
     }
 
+    fn query(&self) -> &[u8] {
+        self.inner.uri().query().unwrap_or_default().as_bytes()
+    }
+
     fn first_header_value(&self, key: &'static [u8]) -> Option<&[u8]> {
         from_utf8(key)
             .ok()
@@ -45,7 +58,7 @@ impl Request<HyperResponse> for RequestAdapter {
         let mut builder = hyper::Response::builder().status(code);
         let map = builder.headers_mut().unwrap();
         headers.for_each(|line| {
-            if let Ok(name) = HeaderName::from_bytes(line.key)
+            if let Ok(name) = HeaderName::from_bytes(line.name())
                 && let Ok(value) = HeaderValue::from_bytes(line.value.as_ref())
             {
                 map.append(name, value);
@@ -1,10 +1,33 @@
+#[cfg(feature = "auth")]
+pub mod auth;
+#[cfg(any(feature = "auth", feature = "csp"))]
+mod base64;
 pub mod builder;
 mod compression;
+mod conditional;
+pub mod cors;
+#[cfg(feature = "csp")]
+mod csp;
+mod date;
+#[cfg(feature = "dev")]
+pub mod dev;
+mod error_pages;
 pub mod errors;
 pub mod github;
 pub mod handler;
+mod header_rules;
 pub mod http;
+mod negotiation;
+mod normalize;
 mod path;
+mod range;
+mod redirects;
+#[cfg(feature = "auth")]
+pub mod signed_urls;
+pub mod source;
+pub mod spa;
+#[cfg(feature = "swappable")]
+pub mod swappable;
 pub mod types;
 
 #[cfg(feature = "hyper")]
@@ -25,7 +48,7 @@ pub mod xitca;
 #[cfg(feature = "salvo")]
 pub mod salvo;
 
-#[cfg(feature = "min_http11_parser")]
+#[cfg(feature = "min_http11")]
 pub mod min_http11;
 
 #[cfg(test)]
@@ -1,12 +1,30 @@
-use crate::handler::{Entry, Handler};
-use crate::http::headers::{Line, LOCATION};
+use crate::conditional::Preconditions;
+use crate::cors::{cors_headers, options_headers, CorsPolicy};
+use crate::error_pages::error_page_headers;
+use crate::handler::{Entry, Fallback, Handler};
+use crate::http::headers::{Line, CONTENT_ENCODING, CONTENT_LENGTH, LOCATION};
 use crate::http::response::StatusCode;
+use crate::normalize::{normalize_path, query};
+use crate::range::{range_response, RangeResponse};
+#[cfg(feature = "swappable")]
+use crate::swappable::SwappableHandler;
 use min_http11_parser::error::Error;
 use min_http11_parser::method::Method;
 use min_http11_parser::parser::{BodyEncoding, Parser};
+use min_http11_parser::request::KnownHeaders;
 use tokio::io::{AsyncBufRead, AsyncWrite, AsyncWriteExt};
 
-pub struct Accepted<'a>(&'a Entry);
+pub struct Accepted<'a>(Target<'a>);
+
+enum Target<'a> {
+    // entry, with its route for the auth policy and error page lookups
+    Entry(&'a str, &'a Entry, Option<&'a CorsPolicy>),
+    // redirection to the canonical form of the request path
+    Redirect(String),
+    BadRequest,
+    // missing or invalid signature for a path under a signed prefix, with the error page
+    Forbidden(Option<&'a Entry>),
+}
 
 impl Handler {
     pub async fn read_request_line<'a, R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin>(
@@ -35,10 +53,58 @@ impl Handler {
         }
     }
 
+    /// Handles the rest of the request after the request line, with `handle_path` if there's an
+    /// entry for the request path, and with the fallbacks and error pages otherwise.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn handle_request<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin>(
+        &self,
+        method: &Method,
+        path: &[u8],
+        parser: &Parser,
+        reader: &mut R,
+        writer: &mut W,
+        buffer1: &mut Vec<u8>,
+        buffer2: &mut Vec<u8>,
+    ) -> Option<()> {
+        if let Some(accepted) = self.accept_bytes(path) {
+            self.handle_path(method, accepted, parser, reader, writer, buffer1, buffer2)
+                .await
+        } else {
+            self.not_found(method, Some(path), parser, reader, writer, buffer1)
+                .await
+        }
+    }
+
+    /// Returns None if there's no entry for the request path.
     pub fn accept(&self, path: &str) -> Option<Accepted<'_>> {
-        self.paths.get(path).map(Accepted)
+        self.accept_bytes(path.as_bytes())
+    }
+
+    /// Returns None if there's no entry for the request path, as it appears in the request line
+    /// (percent-encoded, with the query).
+    pub fn accept_bytes(&self, path: &[u8]) -> Option<Accepted<'_>> {
+        let Some(normalized) = normalize_path(path) else {
+            return Some(Accepted(Target::BadRequest));
+        };
+        if self.canonical_redirects
+            && let Some(location) = normalized.canonical
+        {
+            return Some(Accepted(Target::Redirect(location)));
+        }
+        if !self.is_signature_valid(normalized.path.as_ref(), query(path)) {
+            return Some(Accepted(Target::Forbidden(
+                self.error_page(normalized.path.as_bytes(), StatusCode::Forbidden),
+            )));
+        }
+        let path = normalized.path.as_ref();
+        self.paths
+            .get_key_value(path)
+            .map(|(route, entry)| Accepted(Target::Entry(route, entry, self.cors.find(path))))
     }
 
+    /// Answers with a 404 (or 400 for methods other than GET, HEAD and OPTIONS). The request path
+    /// isn't known, so the fallbacks and `_redirects` rules are not applied.
+    #[deprecated(note = "use handle_request, which applies the fallbacks and error pages")]
     pub async fn handle_not_found<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin>(
         &self,
         method: &Method,
@@ -46,9 +112,23 @@ impl Handler {
         reader: &mut R,
         writer: &mut W,
         buffer: &mut Vec<u8>,
+    ) -> Option<()> {
+        self.not_found(method, None, parser, reader, writer, buffer)
+            .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn not_found<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin>(
+        &self,
+        method: &Method,
+        path: Option<&[u8]>,
+        parser: &Parser,
+        reader: &mut R,
+        writer: &mut W,
+        buffer: &mut Vec<u8>,
     ) -> Option<()> {
         match method {
-            Method::Head | Method::Get => {}
+            Method::Head | Method::Get | Method::Options => {}
             _ => {
                 Self::write_status_line(writer, StatusCode::BadRequest).await?;
                 self.write_error_headers(writer, true).await?;
@@ -76,9 +156,113 @@ impl Handler {
             self.write_error_headers(writer, true).await?;
             return None;
         }
-        Self::write_status_line(writer, StatusCode::NotFound).await?;
-        self.write_error_headers(writer, true).await?;
-        Some(())
+        let Some(path) = path else {
+            let page = self.error_page(b"/", StatusCode::NotFound);
+            return self
+                .write_error(
+                    writer,
+                    method,
+                    StatusCode::NotFound,
+                    page,
+                    &known_headers,
+                    &[],
+                    true,
+                )
+                .await;
+        };
+        let query = query(path);
+        let path = normalize_path(path).map(|it| it.path).unwrap_or_default();
+        let path = path.as_ref();
+        if let Some(challenges) = self
+            .challenges(path, known_headers.authorization)
+            .filter(|_| !Self::is_preflight(method, &known_headers))
+        {
+            let page = self.error_page(path.as_bytes(), StatusCode::Unauthorized);
+            return self
+                .write_error(
+                    writer,
+                    method,
+                    StatusCode::Unauthorized,
+                    page,
+                    &known_headers,
+                    &challenges,
+                    false,
+                )
+                .await;
+        }
+        if let Method::Options = method {
+            // a preflight doesn't carry the Accept header of the actual request, see
+            // Handler::handle_options
+            if let Some(Fallback::Entry(..)) = self.fallback(path, Some(b"text/html")) {
+                let headers = options_headers(
+                    self.cors.find(path),
+                    known_headers.origin,
+                    known_headers.access_control_request_method,
+                );
+                Self::write_status_line(writer, StatusCode::NoContent).await?;
+                Self::write_headers(writer, headers.iter(), false).await?;
+                return Some(());
+            }
+        } else {
+            match self.fallback(path, known_headers.accept) {
+                Some(Fallback::Entry(target, entry, fallback)) => {
+                    // the target can be under a protected or signed prefix that the request path
+                    // isn't
+                    if let Some(challenges) = self.challenges(target, known_headers.authorization) {
+                        let page = self.error_page(path.as_bytes(), StatusCode::Unauthorized);
+                        return self
+                            .write_error(
+                                writer,
+                                method,
+                                StatusCode::Unauthorized,
+                                page,
+                                &known_headers,
+                                &challenges,
+                                false,
+                            )
+                            .await;
+                    }
+                    if !self.is_signature_valid(target, query) {
+                        let page = self.error_page(path.as_bytes(), StatusCode::Forbidden);
+                        return self
+                            .write_error(
+                                writer,
+                                method,
+                                StatusCode::Forbidden,
+                                page,
+                                &known_headers,
+                                &[],
+                                false,
+                            )
+                            .await;
+                    }
+                    let mut extra_headers =
+                        cors_headers(self.cors.find(path), known_headers.origin);
+                    extra_headers.extend(fallback.and_then(|it| it.headers()));
+                    let is_get = matches!(method, Method::Get);
+                    return Self::write_entry(writer, entry, is_get, &known_headers, &extra_headers)
+                        .await;
+                }
+                Some(Fallback::Redirect(status, location)) => {
+                    Self::write_status_line(writer, status).await?;
+                    Self::write_headers(writer, self.redirect_headers(location).iter(), false)
+                        .await?;
+                    return Some(());
+                }
+                None => {}
+            }
+        }
+        let page = self.error_page(path.as_bytes(), StatusCode::NotFound);
+        self.write_error(
+            writer,
+            method,
+            StatusCode::NotFound,
+            page,
+            &known_headers,
+            &[],
+            true,
+        )
+        .await
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -92,9 +276,8 @@ impl Handler {
         buffer1: &mut Vec<u8>,
         buffer2: &mut Vec<u8>,
     ) -> Option<()> {
-        let entry = accepted.0;
         match method {
-            Method::Head | Method::Get => {}
+            Method::Head | Method::Get | Method::Options => {}
             _ => {
                 Self::write_status_line(writer, StatusCode::BadRequest).await?;
                 self.write_error_headers(writer, true).await?;
@@ -117,7 +300,7 @@ impl Handler {
         };
         let is_get = match method {
             Method::Get => true,
-            Method::Head => false,
+            Method::Head | Method::Options => false,
             _ => {
                 Self::write_status_line(writer, StatusCode::BadRequest).await?;
                 self.write_error_headers(writer, true).await?;
@@ -141,33 +324,172 @@ impl Handler {
                 self.write_error_headers(writer, true).await?;
             }
         }
+        let (route, entry, cors) = match accepted.0 {
+            Target::Entry(route, entry, cors) => (route, entry, cors),
+            Target::Redirect(location) => {
+                Self::write_status_line(writer, StatusCode::PermanentRedirect).await?;
+                Self::write_headers(writer, self.redirect_headers(location).iter(), false).await?;
+                return Some(());
+            }
+            Target::BadRequest => {
+                Self::write_status_line(writer, StatusCode::BadRequest).await?;
+                self.write_error_headers(writer, true).await?;
+                return None;
+            }
+            Target::Forbidden(page) => {
+                return self
+                    .write_error(
+                        writer,
+                        method,
+                        StatusCode::Forbidden,
+                        page,
+                        &known_headers,
+                        &[],
+                        false,
+                    )
+                    .await;
+            }
+        };
+        if let Some(challenges) = self
+            .challenges(route, known_headers.authorization)
+            .filter(|_| !Self::is_preflight(method, &known_headers))
+        {
+            let page = self.error_page(route.as_bytes(), StatusCode::Unauthorized);
+            return self
+                .write_error(
+                    writer,
+                    method,
+                    StatusCode::Unauthorized,
+                    page,
+                    &known_headers,
+                    &challenges,
+                    false,
+                )
+                .await;
+        }
+        if let Method::Options = method {
+            let headers = options_headers(
+                cors,
+                known_headers.origin,
+                known_headers.access_control_request_method,
+            );
+            Self::write_status_line(writer, StatusCode::NoContent).await?;
+            Self::write_headers(writer, headers.iter(), false).await?;
+            return Some(());
+        }
+        let cors = cors_headers(cors, known_headers.origin);
+        Self::write_entry(writer, entry, is_get, &known_headers, &cors).await
+    }
+
+    // Writes an error response, with the body of the error page if there's one.
+    #[allow(clippy::too_many_arguments)]
+    async fn write_error<W: AsyncWrite + Unpin>(
+        &self,
+        writer: &mut W,
+        method: &Method,
+        status: StatusCode,
+        page: Option<&Entry>,
+        known_headers: &KnownHeaders<'_>,
+        extra_headers: &[Line],
+        close: bool,
+    ) -> Option<()> {
+        Self::write_status_line(writer, status).await?;
+        if let Some(page) = page {
+            let encoded = page.encoded(known_headers.accept_encoding);
+            Self::write_headers(
+                writer,
+                error_page_headers(self.error_headers, page, encoded).chain(extra_headers.iter()),
+                close,
+            )
+            .await?;
+            if let (Method::Get, Some(body)) = (method, page.body(encoded)) {
+                Self::write_body(writer, body).await?;
+            }
+        } else {
+            Self::write_headers(
+                writer,
+                self.error_headers.iter().chain(extra_headers.iter()),
+                close,
+            )
+            .await?;
+        }
+        Some(())
+    }
+
+    // Preflight requests are sent without credentials.
+    fn is_preflight(method: &Method, known_headers: &KnownHeaders<'_>) -> bool {
+        matches!(method, Method::Options) && known_headers.access_control_request_method.is_some()
+    }
+
+    async fn write_entry<W: AsyncWrite + Unpin>(
+        writer: &mut W,
+        entry: &Entry,
+        is_get: bool,
+        known_headers: &KnownHeaders<'_>,
+        extra_headers: &[Line],
+    ) -> Option<()> {
         let headers = &entry.headers;
         if entry.etag.is_some() {
-            let etag = entry.etag.as_ref().map(|it| it.as_bytes());
-            let none_match = known_headers.if_none_match;
-            let if_match = known_headers.if_match;
-            if none_match.is_some() && none_match == etag {
-                Self::write_status_line(writer, StatusCode::NotModified).await?;
-                Self::write_headers(writer, headers.iter(), false).await?;
-            } else if if_match.is_some() && if_match != etag {
-                Self::write_status_line(writer, StatusCode::PreconditionFailed).await?;
-                Self::write_headers(writer, headers.iter(), false).await?;
-            } else if let Some(ref body) = entry.content {
+            let preconditions = Preconditions {
+                if_match: known_headers.if_match,
+                if_none_match: known_headers.if_none_match,
+                if_modified_since: known_headers.if_modified_since,
+                if_unmodified_since: known_headers.if_unmodified_since,
+            };
+            if let Some(status) = preconditions.evaluate(entry) {
+                Self::write_status_line(writer, status).await?;
+                Self::write_headers(
+                    writer,
+                    headers
+                        .iter()
+                        .filter(|&line| !matches!(line.key, CONTENT_LENGTH | CONTENT_ENCODING))
+                        .chain(extra_headers.iter()),
+                    false,
+                )
+                .await?;
+            } else if is_get
+                && let Some(range) =
+                    range_response(entry, known_headers.range, known_headers.if_range)
+            {
+                match range {
+                    RangeResponse::Partial { headers, body } => {
+                        Self::write_status_line(writer, StatusCode::PartialContent).await?;
+                        Self::write_headers(writer, headers.iter().chain(extra_headers.iter()), false)
+                            .await?;
+                        Self::write_body(writer, body).await?;
+                    }
+                    RangeResponse::NotSatisfiable { headers } => {
+                        Self::write_status_line(writer, StatusCode::RangeNotSatisfiable).await?;
+                        Self::write_headers(writer, headers.iter().chain(extra_headers.iter()), false)
+                            .await?;
+                    }
+                }
+            } else if entry.content.is_some() {
+                let encoded = entry.encoded(known_headers.accept_encoding);
                 Self::write_status_line(writer, StatusCode::OK).await?;
-                Self::write_headers(writer, headers.iter(), false).await?;
-                if is_get {
+                Self::write_headers(writer, entry.headers(encoded).chain(extra_headers.iter()), false)
+                    .await?;
+                if is_get && let Some(body) = entry.body(encoded) {
                     Self::write_body(writer, body).await?;
                 }
             } else if headers.iter().any(|it| it.key == LOCATION) {
                 Self::write_status_line(writer, StatusCode::TemporaryRedirect).await?;
-                Self::write_headers(writer, headers.iter(), false).await?;
+                Self::write_headers(writer, headers.iter().chain(extra_headers.iter()), false).await?;
             } else {
                 Self::write_status_line(writer, StatusCode::NoContent).await?;
-                Self::write_headers(writer, headers.iter(), false).await?;
+                Self::write_headers(
+                    writer,
+                    headers
+                        .iter()
+                        .filter(|&line| !matches!(line.key, CONTENT_LENGTH | CONTENT_ENCODING))
+                        .chain(extra_headers.iter()),
+                    false,
+                )
+                .await?;
             }
         } else {
             Self::write_status_line(writer, StatusCode::PermanentRedirect).await?;
-            Self::write_headers(writer, headers.iter(), false).await?;
+            Self::write_headers(writer, headers.iter().chain(extra_headers.iter()), false).await?;
         }
         Some(())
     }
@@ -180,6 +502,9 @@ impl Handler {
             .write_all(match code {
                 StatusCode::OK => b"HTTP/1.1 200 OK\r\n",
                 StatusCode::NoContent => b"HTTP/1.1 204 No Content\r\n",
+                StatusCode::PartialContent => b"HTTP/1.1 206 Partial Content\r\n",
+                StatusCode::MovedPermanently => b"HTTP/1.1 301 Moved Permanently\r\n",
+                StatusCode::Found => b"HTTP/1.1 302 Found\r\n",
                 StatusCode::NotModified => b"HTTP/1.1 304 Not Modified\r\n",
                 StatusCode::TemporaryRedirect => b"HTTP/1.1 307 Temporary Redirect\r\n",
                 StatusCode::PermanentRedirect => b"HTTP/1.1 308 Permanent Redirect\r\n",
@@ -191,6 +516,7 @@ impl Handler {
                 StatusCode::RequestTimeout => b"HTTP/1.1 408 Request Timeout\r\n",
                 StatusCode::PreconditionFailed => b"HTTP/1.1 412 Precondition Failed\r\n",
                 StatusCode::RequestTooLarge => b"HTTP/1.1 413 Request Too Large\r\n",
+                StatusCode::RangeNotSatisfiable => b"HTTP/1.1 416 Range Not Satisfiable\r\n",
                 StatusCode::TooManyRequests => b"HTTP/1.1 429 Too Many Requests\r\n",
                 StatusCode::InternalServerError => b"HTTP/1.1 500 Internal Server Error\r\n",
                 StatusCode::NotImplemented => b"HTTP/1.1 501 Not Implemented\r\n",
@@ -216,7 +542,7 @@ impl Handler {
         close: bool,
     ) -> Option<()> {
         for line in headers {
-            writer.write_all(line.key).await.ok()?;
+            writer.write_all(line.name()).await.ok()?;
             writer.write_all(b": ").await.ok()?;
             writer.write_all(line.value.as_ref()).await.ok()?;
             writer.write_all(b"\r\n").await.ok()?;
@@ -238,3 +564,35 @@ impl Handler {
         writer.write_all(body.as_ref()).await.ok()
     }
 }
+
+#[cfg(feature = "swappable")]
+impl SwappableHandler {
+    pub async fn read_request_line<'a, R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin>(
+        &self,
+        parser: &Parser,
+        reader: &mut R,
+        writer: &mut W,
+        buffer: &'a mut Vec<u8>,
+    ) -> Option<(Method, &'a [u8])> {
+        self.current()
+            .read_request_line(parser, reader, writer, buffer)
+            .await
+    }
+
+    /// Handles the rest of the request after the request line, with the current handler.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn handle_request<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin>(
+        &self,
+        method: &Method,
+        path: &[u8],
+        parser: &Parser,
+        reader: &mut R,
+        writer: &mut W,
+        buffer1: &mut Vec<u8>,
+        buffer2: &mut Vec<u8>,
+    ) -> Option<()> {
+        self.current()
+            .handle_request(method, path, parser, reader, writer, buffer1, buffer2)
+            .await
+    }
+}
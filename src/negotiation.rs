@@ -0,0 +1,170 @@
+use crate::handler::EncodedContent;
+
+const IDENTITY: &[u8] = b"identity";
+const ANY: &[u8] = b"*";
+
+// Picks the representation to send for the given accept-encoding header value.
+// None means that the identity representation should be used.
+// The encoded representations are expected to be listed in order of preference, which is
+// used to break ties between codings with the same q-value.
+pub(crate) fn preferred_encoding<'a>(
+    accept_encoding: Option<&[u8]>,
+    encoded: &'a [EncodedContent],
+) -> Option<&'a EncodedContent> {
+    let accept_encoding = accept_encoding?;
+    if encoded.is_empty() {
+        return None;
+    }
+    let mut any = None;
+    let mut identity = None;
+    let mut qualities = vec![None; encoded.len()];
+    for (coding, q) in codings(accept_encoding) {
+        if coding == ANY {
+            any = Some(q);
+        } else if coding.eq_ignore_ascii_case(IDENTITY) {
+            identity = Some(q);
+        } else if let Some(i) = encoded
+            .iter()
+            .position(|it| coding.eq_ignore_ascii_case(it.encoding.token()))
+        {
+            qualities[i] = Some(q);
+        }
+    }
+    // identity is always acceptable unless explicitly excluded, but with the lowest preference
+    let identity = identity.or(any).unwrap_or(1);
+    let (best, q) = qualities
+        .into_iter()
+        .map(|q| q.or(any).unwrap_or(0))
+        .enumerate()
+        .fold((None, 0), |(best, best_q), (i, q)| {
+            if q > best_q {
+                (Some(i), q)
+            } else {
+                (best, best_q)
+            }
+        });
+    if q >= identity {
+        best.map(|i| &encoded[i])
+    } else {
+        None
+    }
+}
+
+// Iterates over the codings of an accept-encoding header value with their q-value
+// (in thousandths). Entries with an invalid q-value are skipped.
+fn codings(value: &[u8]) -> impl Iterator<Item = (&[u8], u16)> {
+    value.split(|&b| b == b',').filter_map(|item| {
+        let mut parts = item.split(|&b| b == b';');
+        let coding = parts.next()?.trim_ascii();
+        if coding.is_empty() {
+            return None;
+        }
+        let mut q = 1000;
+        for parameter in parts {
+            let parameter = parameter.trim_ascii();
+            if let Some(value) = parameter
+                .strip_prefix(b"q=")
+                .or_else(|| parameter.strip_prefix(b"Q="))
+            {
+                q = quality(value.trim_ascii())?;
+            }
+        }
+        Some((coding, q))
+    })
+}
+
+fn quality(value: &[u8]) -> Option<u16> {
+    let value = std::str::from_utf8(value).ok()?.parse::<f32>().ok()?;
+    if (0.0..=1.0).contains(&value) {
+        Some((value * 1000.0).round() as u16)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::Encoding;
+    use bytes::Bytes;
+
+    fn encoded(encodings: &[Encoding]) -> Vec<EncodedContent> {
+        encodings
+            .iter()
+            .map(|&encoding| EncodedContent {
+                encoding,
+                headers: vec![],
+                content: Bytes::new(),
+            })
+            .collect()
+    }
+
+    fn preferred(accept_encoding: Option<&[u8]>, encoded: &[EncodedContent]) -> Option<Encoding> {
+        preferred_encoding(accept_encoding, encoded).map(|it| it.encoding)
+    }
+
+    #[test]
+    fn without_header() {
+        let encoded = encoded(&[Encoding::Brotli]);
+        assert_eq!(preferred(None, &encoded), None);
+    }
+
+    #[test]
+    fn without_encoded_content() {
+        assert_eq!(preferred(Some(b"br"), &[]), None);
+    }
+
+    #[test]
+    fn brotli() {
+        let encoded = encoded(&[Encoding::Brotli]);
+        assert_eq!(preferred(Some(b"br"), &encoded), Some(Encoding::Brotli));
+        assert_eq!(
+            preferred(Some(b"gzip, deflate, br"), &encoded),
+            Some(Encoding::Brotli)
+        );
+        assert_eq!(preferred(Some(b"BR"), &encoded), Some(Encoding::Brotli));
+        assert_eq!(preferred(Some(b"*"), &encoded), Some(Encoding::Brotli));
+        assert_eq!(preferred(Some(b"gzip, deflate"), &encoded), None);
+        assert_eq!(preferred(Some(b""), &encoded), None);
+    }
+
+    #[test]
+    fn gzip() {
+        let encoded = encoded(&[Encoding::Brotli, Encoding::Gzip]);
+        assert_eq!(preferred(Some(b"gzip"), &encoded), Some(Encoding::Gzip));
+        assert_eq!(
+            preferred(Some(b"gzip, deflate, br"), &encoded),
+            Some(Encoding::Brotli)
+        );
+        assert_eq!(
+            preferred(Some(b"br;q=0.8, gzip"), &encoded),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(
+            preferred(Some(b"*, br;q=0"), &encoded),
+            Some(Encoding::Gzip)
+        );
+    }
+
+    #[test]
+    fn q_values() {
+        let encoded = encoded(&[Encoding::Brotli]);
+        assert_eq!(preferred(Some(b"br;q=0"), &encoded), None);
+        assert_eq!(preferred(Some(b"br; q=0.000"), &encoded), None);
+        assert_eq!(
+            preferred(Some(b"br;q=0.001"), &encoded),
+            Some(Encoding::Brotli)
+        );
+        assert_eq!(preferred(Some(b"br;q=0.5, identity"), &encoded), None);
+        assert_eq!(
+            preferred(Some(b"br;q=0.5, identity;q=0.4"), &encoded),
+            Some(Encoding::Brotli)
+        );
+        assert_eq!(preferred(Some(b"*;q=0, identity"), &encoded), None);
+        assert_eq!(preferred(Some(b"br;q=2"), &encoded), None);
+        assert_eq!(
+            preferred(Some(b"*, identity;q=0"), &encoded),
+            Some(Encoding::Brotli)
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use std::borrow::Cow;
+
+// Request path after normalization.
+pub(crate) struct NormalizedPath<'a> {
+    // percent-decoded path, without dot segments, duplicate slashes and query
+    pub(crate) path: Cow<'a, str>,
+    // canonical form of the request path (still percent-encoded, with the query),
+    // if it's different from the request path
+    pub(crate) canonical: Option<String>,
+}
+
+// Normalizes a request path: the query is removed, the segments are percent-decoded,
+// and the dot segments and empty segments are removed (/a//./b/../c -> /a/c).
+// A trailing slash is kept.
+// Returns None for paths that should be rejected: paths that don't start with a slash, invalid
+// percent-encoding, encoded slashes or NULs, invalid utf-8 and paths that climb above the root.
+pub(crate) fn normalize_path(path: &[u8]) -> Option<NormalizedPath<'_>> {
+    let end = path
+        .iter()
+        .position(|&b| b == b'?' || b == b'#')
+        .unwrap_or(path.len());
+    let (path, query) = path.split_at(end);
+    let segments = path.strip_prefix(b"/")?;
+    if is_normalized(segments) {
+        return Some(NormalizedPath {
+            path: Cow::Borrowed(std::str::from_utf8(path).ok()?),
+            canonical: None,
+        });
+    }
+    let mut stack: Vec<(&[u8], Vec<u8>)> = vec![];
+    let mut trailing_slash = false;
+    for raw in segments.split(|&b| b == b'/') {
+        let decoded = percent_decode(raw)?;
+        if decoded.iter().any(|&b| b == b'/' || b == 0) {
+            return None;
+        }
+        trailing_slash = true;
+        match decoded.as_slice() {
+            b"" | b"." => {}
+            b".." => {
+                stack.pop()?;
+            }
+            _ => {
+                stack.push((raw, decoded));
+                trailing_slash = false;
+            }
+        }
+    }
+    let join = |segments: &mut dyn Iterator<Item = &[u8]>| {
+        let mut joined = vec![b'/'];
+        for (i, segment) in segments.enumerate() {
+            if i > 0 {
+                joined.push(b'/');
+            }
+            joined.extend_from_slice(segment);
+        }
+        if trailing_slash && joined.len() > 1 {
+            joined.push(b'/');
+        }
+        joined
+    };
+    let decoded = join(&mut stack.iter().map(|(_, decoded)| decoded.as_slice()));
+    let mut canonical = join(&mut stack.iter().map(|(raw, _)| *raw));
+    canonical.extend_from_slice(query);
+    Some(NormalizedPath {
+        path: Cow::Owned(String::from_utf8(decoded).ok()?),
+        canonical: if canonical[..canonical.len() - query.len()] == *path {
+            None
+        } else {
+            String::from_utf8(canonical).ok()
+        },
+    })
+}
+
+// Whether the path (without its leading slash) doesn't need any transformation.
+fn is_normalized(segments: &[u8]) -> bool {
+    if segments.iter().any(|&b| b == b'%' || b == 0) {
+        return false;
+    }
+    let mut iter = segments.split(|&b| b == b'/').peekable();
+    while let Some(segment) = iter.next() {
+        match segment {
+            b"." | b".." => return false,
+            b"" if iter.peek().is_some() => return false,
+            _ => {}
+        }
+    }
+    true
+}
+
+fn percent_decode(segment: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(segment.len());
+    let mut iter = segment.iter();
+    while let Some(&b) = iter.next() {
+        if b == b'%' {
+            let high = hex_digit(*iter.next()?)?;
+            let low = hex_digit(*iter.next()?)?;
+            decoded.push((high << 4) | low);
+        } else {
+            decoded.push(b);
+        }
+    }
+    Some(decoded)
+}
+
+// Percent-encodes a decoded path (the slashes are kept).
+pub(crate) fn percent_encode(value: &str, result: &mut String) {
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~!$&'()*+,;=:@".contains(&b) {
+            result.push(b as char);
+        } else {
+            result.push_str(&format!("%{b:02X}"));
+        }
+    }
+}
+
+// Query of a request path (without the ?), empty if there's none.
+pub(crate) fn query(path: &[u8]) -> &[u8] {
+    let Some(start) = path.iter().position(|&b| b == b'?') else {
+        return b"";
+    };
+    let query = &path[start + 1..];
+    let end = query.iter().position(|&b| b == b'#').unwrap_or(query.len());
+    &query[..end]
+}
+
+fn hex_digit(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalized(path: &str) -> Option<(String, Option<String>)> {
+        normalize_path(path.as_bytes()).map(|it| (it.path.into_owned(), it.canonical))
+    }
+
+    #[test]
+    fn unchanged() {
+        for path in ["/", "/a", "/a/b/", "/a/b.c", "/.well-known/a"] {
+            assert_eq!(normalized(path), Some((path.to_string(), None)));
+        }
+        assert!(matches!(
+            normalize_path(b"/a/b").map(|it| it.path),
+            Some(Cow::Borrowed("/a/b"))
+        ));
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(
+            normalized("/caf%C3%A9.png"),
+            Some(("/café.png".to_string(), None))
+        );
+        assert_eq!(normalized("/a%20b"), Some(("/a b".to_string(), None)));
+        assert_eq!(normalized("/a%2fb"), None);
+        assert_eq!(normalized("/a%2Fb"), None);
+        assert_eq!(normalized("/a%00"), None);
+        assert_eq!(normalized("/a%zz"), None);
+        assert_eq!(normalized("/a%2"), None);
+        assert_eq!(normalized("/%FF"), None);
+    }
+
+    #[test]
+    fn segments() {
+        let canonical = |path: &str, expected: &str, location: &str| {
+            assert_eq!(
+                normalized(path),
+                Some((expected.to_string(), Some(location.to_string())))
+            );
+        };
+        canonical("//favicon.png", "/favicon.png", "/favicon.png");
+        canonical("/a/./b", "/a/b", "/a/b");
+        canonical("/a/b/../c", "/a/c", "/a/c");
+        canonical("/a//b//", "/a/b/", "/a/b/");
+        canonical("/a/b/.", "/a/b/", "/a/b/");
+        canonical("/a/b/..", "/a/", "/a/");
+        canonical("/a/..", "/", "/");
+        canonical("/a/%2e%2E/b", "/b", "/b");
+        canonical("/a/../caf%C3%A9?x=1", "/café", "/caf%C3%A9?x=1");
+        assert_eq!(normalized("/a?x=/../"), Some(("/a".to_string(), None)));
+    }
+
+    #[test]
+    fn rejected() {
+        assert_eq!(normalized(""), None);
+        assert_eq!(normalized("a/b"), None);
+        assert_eq!(normalized("/.."), None);
+        assert_eq!(normalized("/a/../.."), None);
+        assert_eq!(normalized("/%2e%2e/a"), None);
+        assert_eq!(normalized("/a\0"), None);
+    }
+}
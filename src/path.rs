@@ -1,16 +1,74 @@
+use crate::builder::UrlStyle;
+
+// Path of an entry, relative to the path prefix.
+// Html pages and redirections get a trailing slash, and directory indices (a/index.html) are
+// served at their directory path (/a/).
 pub(crate) fn path(zip_prefix: &str, name: &str) -> String {
-    let name = &name[zip_prefix.len()..];
-    let start = name.find(|c| c != '.' && c != '/').unwrap_or(0);
-    if name.ends_with(".html") {
-        if &name[start..] == "index.html" {
+    let name = relative_name(zip_prefix, name);
+    if let Some(stem) = name.strip_suffix(".html") {
+        if stem == "index" {
             "/".to_string()
+        } else if let Some(directory) = stem.strip_suffix("/index") {
+            format!("/{directory}/")
         } else {
-            format!("/{}/", &name[start..name.len() - 5])
+            format!("/{stem}/")
         }
     } else if name.ends_with(".307") || name.ends_with(".308") {
-        format!("/{}/", &name[start..name.len() - 4])
+        format!("/{}/", &name[..name.len() - 4])
+    } else {
+        format!("/{name}")
+    }
+}
+
+// Name of the entry relative to the zip prefix, without leading slashes or ./ segments.
+pub(crate) fn relative_name<'a>(zip_prefix: &str, name: &'a str) -> &'a str {
+    let mut name = &name[zip_prefix.len()..];
+    while let Some(rest) = name.strip_prefix("./").or_else(|| name.strip_prefix('/')) {
+        name = rest;
+    }
+    name
+}
+
+// Whether the entry has a hidden (dot) file or directory in its relative name.
+pub(crate) fn is_hidden(relative_name: &str) -> bool {
+    relative_name.starts_with('.') || relative_name.contains("/.")
+}
+
+// Name of an html page relative to the zip prefix.
+pub(crate) fn html_name<'a>(zip_prefix: &str, name: &'a str) -> Option<&'a str> {
+    let name = relative_name(zip_prefix, name);
+    name.ends_with(".html").then_some(name)
+}
+
+// Route of the content of an entry, for the url style.
+// With the extension style, html pages keep their extension and directory indices are served at
+// the directory path with a trailing slash.
+pub(crate) fn entry_route(
+    url_style: UrlStyle,
+    path_prefix: &str,
+    zip_prefix: &str,
+    name: &str,
+    path: &str,
+) -> String {
+    match (url_style, html_name(zip_prefix, name)) {
+        (UrlStyle::Extension, Some(html_name)) => {
+            if filename(html_name) == "index.html" {
+                format!("{path_prefix}{path}")
+            } else {
+                format!("{path_prefix}/{html_name}")
+            }
+        }
+        _ => route(path_prefix, path),
+    }
+}
+
+// Route of the content for a path returned by `path`.
+// Paths with a trailing slash are served without it, except for the root when there's no prefix.
+pub(crate) fn route(path_prefix: &str, path: &str) -> String {
+    if path.ends_with('/') && (path.len() > 1 || !path_prefix.is_empty()) {
+        format!("{path_prefix}{}", &path[..path.len() - 1])
     } else {
-        format!("/{}", &name[start..])
+        format!("{path_prefix}{path}")
     }
 }
 
@@ -44,6 +102,50 @@ mod tests {
         assert_eq!(path(prefix, "/prefix/a/b/c.jpg"), "/a/b/c.jpg");
     }
 
+    #[test]
+    fn directory_index() {
+        assert_eq!(path("", "docs/index.html"), "/docs/");
+        assert_eq!(path("", "docs/a/index.html"), "/docs/a/");
+        assert_eq!(path("/prefix/", "/prefix/docs/index.html"), "/docs/");
+        assert_eq!(path("", "docs/myindex.html"), "/docs/myindex/");
+        assert_eq!(path("", "docs/index.json"), "/docs/index.json");
+        assert_eq!(route("", &path("", "docs/index.html")), "/docs");
+        assert_eq!(
+            route("/prefix", &path("", "docs/index.html")),
+            "/prefix/docs"
+        );
+    }
+
+    #[test]
+    fn hidden_path() {
+        assert_eq!(
+            path("", ".well-known/security.txt"),
+            "/.well-known/security.txt"
+        );
+        assert_eq!(path("", "./a/b/c.jpg"), "/a/b/c.jpg");
+        assert_eq!(
+            relative_name("/prefix", "/prefix/.well-known/a"),
+            ".well-known/a"
+        );
+        assert!(is_hidden(".well-known/a"));
+        assert!(is_hidden("a/.git/config"));
+        assert!(!is_hidden("a/b.c"));
+    }
+
+    #[test]
+    fn route_with_prefix() {
+        assert_eq!(route("/prefix", "/"), "/prefix");
+        assert_eq!(route("/prefix", "/a/b/"), "/prefix/a/b");
+        assert_eq!(route("/prefix", "/a/b/c.jpg"), "/prefix/a/b/c.jpg");
+    }
+
+    #[test]
+    fn route_no_prefix() {
+        assert_eq!(route("", "/"), "/");
+        assert_eq!(route("", "/a/b/"), "/a/b");
+        assert_eq!(route("", "/a/b/c.jpg"), "/a/b/c.jpg");
+    }
+
     #[test]
     fn path_no_prefix() {
         assert_eq!(path("", "/index.html"), "/");
@@ -0,0 +1,237 @@
+use crate::conditional::if_range_matches;
+use crate::handler::Entry;
+use crate::http::headers::{
+    CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, Line, VARY,
+};
+use bytes::{BufMut, Bytes, BytesMut};
+
+// Requests with more ranges than this are served in full.
+const MAX_RANGES: usize = 16;
+const BOUNDARY: &str = "6a0f2d7c91e43b58";
+
+pub(crate) enum RangeResponse {
+    Partial { headers: Vec<Line>, body: Bytes },
+    NotSatisfiable { headers: Vec<Line> },
+}
+
+// Evaluates the range and if-range request headers against the identity representation.
+// None means that the range header should be ignored and the full content sent.
+pub(crate) fn range_response(
+    entry: &Entry,
+    range: Option<&[u8]>,
+    if_range: Option<&[u8]>,
+) -> Option<RangeResponse> {
+    let range = range?;
+    let content = entry.content.as_ref()?;
+    if let Some(if_range) = if_range
+        && !if_range_matches(if_range, entry.etag.as_deref(), entry.last_modified)
+    {
+        return None;
+    }
+    let ranges = parse(range, content.len())?;
+    if ranges.is_empty() {
+        let mut headers = headers(entry, &[CONTENT_LENGTH, CONTENT_ENCODING, VARY]);
+        headers.push(Line::with_owned_value(
+            CONTENT_RANGE,
+            format!("bytes */{}", content.len()).into_bytes(),
+        ));
+        headers.push(Line::with_slice_value(CONTENT_LENGTH, b"0"));
+        return Some(RangeResponse::NotSatisfiable { headers });
+    }
+    if let [(first, last)] = ranges[..] {
+        let mut headers = headers(entry, &[CONTENT_LENGTH, CONTENT_ENCODING, VARY]);
+        headers.push(content_range(first, last, content.len()));
+        headers.push(Line::with_owned_value(
+            CONTENT_LENGTH,
+            format!("{}", last + 1 - first).into_bytes(),
+        ));
+        return Some(RangeResponse::Partial {
+            headers,
+            body: content.slice(first..=last),
+        });
+    }
+    let content_type = entry
+        .headers
+        .iter()
+        .find(|it| it.key == CONTENT_TYPE)
+        .map(|it| it.value.as_ref());
+    let mut body = BytesMut::new();
+    for &(first, last) in &ranges {
+        body.put_slice(b"\r\n--");
+        body.put_slice(BOUNDARY.as_bytes());
+        body.put_slice(b"\r\n");
+        if let Some(content_type) = content_type {
+            body.put_slice(CONTENT_TYPE);
+            body.put_slice(b": ");
+            body.put_slice(content_type);
+            body.put_slice(b"\r\n");
+        }
+        let content_range = content_range(first, last, content.len());
+        body.put_slice(content_range.key);
+        body.put_slice(b": ");
+        body.put_slice(content_range.value.as_ref());
+        body.put_slice(b"\r\n\r\n");
+        body.put_slice(&content[first..=last]);
+    }
+    body.put_slice(b"\r\n--");
+    body.put_slice(BOUNDARY.as_bytes());
+    body.put_slice(b"--\r\n");
+    let mut headers = headers(
+        entry,
+        &[CONTENT_LENGTH, CONTENT_ENCODING, VARY, CONTENT_TYPE],
+    );
+    headers.push(Line::with_owned_value(
+        CONTENT_TYPE,
+        format!("multipart/byteranges; boundary={BOUNDARY}").into_bytes(),
+    ));
+    headers.push(Line::with_owned_value(
+        CONTENT_LENGTH,
+        format!("{}", body.len()).into_bytes(),
+    ));
+    Some(RangeResponse::Partial {
+        headers,
+        body: body.freeze(),
+    })
+}
+
+fn headers(entry: &Entry, excluded: &[&[u8]]) -> Vec<Line> {
+    entry
+        .headers
+        .iter()
+        .filter(|line| !excluded.contains(&line.key))
+        .cloned()
+        .collect()
+}
+
+fn content_range(first: usize, last: usize, len: usize) -> Line {
+    Line::with_owned_value(
+        CONTENT_RANGE,
+        format!("bytes {first}-{last}/{len}").into_bytes(),
+    )
+}
+
+// Parses a range header value into a sorted list of satisfiable ranges (inclusive bounds), with
+// the overlapping and adjacent ranges merged.
+// None means that the header should be ignored (invalid syntax, unknown unit, too many ranges,
+// or ranges that add up to more than the content).
+// An empty list means that none of the ranges is satisfiable.
+fn parse(value: &[u8], len: usize) -> Option<Vec<(usize, usize)>> {
+    let value = value.trim_ascii();
+    if value.len() < 6 || !value[..6].eq_ignore_ascii_case(b"bytes=") {
+        return None;
+    }
+    let mut ranges = vec![];
+    for spec in value[6..].split(|&b| b == b',') {
+        let spec = spec.trim_ascii();
+        if spec.is_empty() {
+            continue;
+        }
+        if ranges.len() == MAX_RANGES {
+            return None;
+        }
+        let dash = spec.iter().position(|&b| b == b'-')?;
+        let (first, last) = (&spec[..dash], &spec[dash + 1..]);
+        if first.is_empty() {
+            let suffix = number(last)?;
+            if suffix > 0 && len > 0 {
+                ranges.push((len.saturating_sub(suffix), len - 1));
+            }
+        } else {
+            let first = number(first)?;
+            let last = if last.is_empty() {
+                usize::MAX
+            } else {
+                number(last)?
+            };
+            if last < first {
+                return None;
+            }
+            if first < len {
+                ranges.push((first, last.min(len - 1)));
+            }
+        }
+    }
+    // overlapping ranges would make the response larger than the full content
+    let total = ranges
+        .iter()
+        .map(|&(first, last)| last + 1 - first)
+        .sum::<usize>();
+    if total > len {
+        return None;
+    }
+    ranges.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(ranges.len());
+    for (first, last) in ranges {
+        match merged.last_mut() {
+            Some((_, previous)) if first <= *previous + 1 => *previous = last.max(*previous),
+            _ => merged.push((first, last)),
+        }
+    }
+    Some(merged)
+}
+
+fn number(value: &[u8]) -> Option<usize> {
+    if value.is_empty() || !value.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    std::str::from_utf8(value).ok()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_range() {
+        assert_eq!(parse(b"bytes=0-499", 1000), Some(vec![(0, 499)]));
+        assert_eq!(parse(b"bytes=500-", 1000), Some(vec![(500, 999)]));
+        assert_eq!(parse(b"bytes=-200", 1000), Some(vec![(800, 999)]));
+        assert_eq!(parse(b"bytes=-2000", 1000), Some(vec![(0, 999)]));
+        assert_eq!(parse(b"bytes=900-2000", 1000), Some(vec![(900, 999)]));
+        assert_eq!(parse(b"Bytes=0-0", 1000), Some(vec![(0, 0)]));
+    }
+
+    #[test]
+    fn multiple_ranges() {
+        assert_eq!(
+            parse(b"bytes=0-9, 20-29,-5", 100),
+            Some(vec![(0, 9), (20, 29), (95, 99)])
+        );
+        assert_eq!(parse(b"bytes=0-9, 200-299", 100), Some(vec![(0, 9)]));
+    }
+
+    #[test]
+    fn merged_ranges() {
+        assert_eq!(
+            parse(b"bytes=20-29,0-9,5-14,-5", 100),
+            Some(vec![(0, 14), (20, 29), (95, 99)])
+        );
+        assert_eq!(parse(b"bytes=0-9,10-19", 100), Some(vec![(0, 19)]));
+        assert_eq!(parse(b"bytes=-50,0-49", 100), Some(vec![(0, 99)]));
+        assert_eq!(parse(b"bytes=0-,0-", 100), None);
+        assert_eq!(parse(b"bytes=0-60,40-99", 100), None);
+    }
+
+    #[test]
+    fn not_satisfiable() {
+        assert_eq!(parse(b"bytes=1000-", 1000), Some(vec![]));
+        assert_eq!(parse(b"bytes=-0", 1000), Some(vec![]));
+        assert_eq!(parse(b"bytes=0-", 0), Some(vec![]));
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(parse(b"items=0-1", 1000), None);
+        assert_eq!(parse(b"bytes=1-0", 1000), None);
+        assert_eq!(parse(b"bytes=a-b", 1000), None);
+        assert_eq!(parse(b"bytes=1", 1000), None);
+        assert_eq!(parse(b"bytes=+1-2", 1000), None);
+        assert_eq!(
+            parse(
+                b"bytes=0-0,1-1,2-2,3-3,4-4,5-5,6-6,7-7,8-8,9-9,10-10,11-11,12-12,13-13,14-14,15-15,16-16",
+                1000
+            ),
+            None
+        );
+    }
+}
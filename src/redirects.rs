@@ -0,0 +1,280 @@
+use crate::errors::Result;
+use crate::http::response::StatusCode;
+use crate::normalize::percent_encode;
+use std::collections::HashMap;
+
+enum Segment {
+    Literal(String),
+    // :name
+    Placeholder(String),
+    // *, only as the last segment
+    Splat,
+}
+
+struct Rule {
+    // position of the rule in the file, the first matching rule wins
+    line: usize,
+    to: String,
+    status: StatusCode,
+}
+
+// Target of the rule matching a request path.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum RedirectTarget {
+    Redirect {
+        status: StatusCode,
+        location: String,
+    },
+    // path of the entry that should be served instead
+    Rewrite(String),
+}
+
+// Rules from a `_redirects` file.
+// Rules without placeholders or splat are looked up by path, and the pattern rules are only
+// tried in order on a miss.
+#[derive(Default)]
+pub(crate) struct Redirects {
+    exact: HashMap<String, Rule>,
+    patterns: Vec<(Vec<Segment>, Rule)>,
+}
+
+impl Redirects {
+    // Parses the content of a `_redirects` file, with one `from to [status]` rule per line.
+    // Blank lines and lines starting with # are ignored.
+    // The status defaults to 301, and 200 rewrites the request to another entry.
+    pub(crate) fn parse(content: &str, path_prefix: &str) -> Result<Self> {
+        let mut redirects = Self::default();
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || format!("invalid _redirects rule on line {}: {line}", i + 1);
+            let (from, to, status) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [from, to] => (from, to, "301"),
+                [from, to, status] => (from, to, status),
+                _ => return Err(invalid().into()),
+            };
+            let status = match status {
+                "200" => StatusCode::OK,
+                "301" => StatusCode::MovedPermanently,
+                "302" => StatusCode::Found,
+                "307" => StatusCode::TemporaryRedirect,
+                "308" => StatusCode::PermanentRedirect,
+                _ => return Err(invalid().into()),
+            };
+            if !from.starts_with('/') || (status == StatusCode::OK && !to.starts_with('/')) {
+                return Err(invalid().into());
+            }
+            let to = if to.starts_with('/') {
+                format!("{path_prefix}{to}")
+            } else {
+                to.to_string()
+            };
+            let rule = Rule {
+                line: i,
+                to,
+                status,
+            };
+            let from = format!("{path_prefix}{}", trim_trailing_slash(from));
+            let segments = from[1..]
+                .split('/')
+                .map(|it| match it {
+                    "*" => Segment::Splat,
+                    _ => match it.strip_prefix(':') {
+                        Some(name) => Segment::Placeholder(name.to_string()),
+                        None => Segment::Literal(it.to_string()),
+                    },
+                })
+                .collect::<Vec<_>>();
+            let splat = segments.iter().position(|it| matches!(it, Segment::Splat));
+            if splat.is_some_and(|it| it != segments.len() - 1) {
+                return Err(invalid().into());
+            }
+            if segments.iter().all(|it| matches!(it, Segment::Literal(_))) {
+                redirects.exact.entry(from).or_insert(rule);
+            } else {
+                redirects.patterns.push((segments, rule));
+            }
+        }
+        Ok(redirects)
+    }
+
+    // Rewrite targets that don't depend on the request path.
+    pub(crate) fn static_rewrites(&self) -> impl Iterator<Item = &str> {
+        self.exact
+            .values()
+            .chain(self.patterns.iter().map(|(_, rule)| rule))
+            .filter(|rule| rule.status == StatusCode::OK && !rule.to.contains(':'))
+            .map(|rule| rule.to.as_str())
+    }
+
+    pub(crate) fn find(&self, path: &str) -> Option<RedirectTarget> {
+        let path = trim_trailing_slash(path);
+        let exact = self.exact.get(path);
+        let (rule, captures) = self
+            .patterns
+            .iter()
+            .take_while(|(_, rule)| exact.is_none_or(|it| rule.line < it.line))
+            .find_map(|(segments, rule)| captures(segments, path).map(|it| (rule, it)))
+            .or_else(|| exact.map(|rule| (rule, vec![])))?;
+        if rule.status == StatusCode::OK {
+            Some(RedirectTarget::Rewrite(substitute(
+                &rule.to, &captures, false,
+            )))
+        } else {
+            Some(RedirectTarget::Redirect {
+                status: rule.status,
+                location: substitute(&rule.to, &captures, true),
+            })
+        }
+    }
+}
+
+fn trim_trailing_slash(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        path => path,
+    }
+}
+
+// Values captured by the placeholders and splat of a pattern, if it matches the path.
+fn captures<'a>(segments: &'a [Segment], path: &'a str) -> Option<Vec<(&'a str, &'a str)>> {
+    let mut captures = vec![];
+    let mut rest = path.strip_prefix('/')?;
+    for (i, segment) in segments.iter().enumerate() {
+        if let Segment::Splat = segment {
+            captures.push(("splat", rest));
+            return Some(captures);
+        }
+        let end = rest.find('/').unwrap_or(rest.len());
+        let current = &rest[..end];
+        match segment {
+            Segment::Literal(literal) if literal == current => {}
+            Segment::Placeholder(name) if !current.is_empty() => captures.push((name, current)),
+            _ => return None,
+        }
+        rest = match rest[end..].strip_prefix('/') {
+            Some(next) if i + 1 < segments.len() => next,
+            _ => &rest[end..],
+        };
+    }
+    rest.is_empty().then_some(captures)
+}
+
+// Replaces the :name placeholders (and :splat) in the target with their captured values.
+fn substitute(to: &str, captures: &[(&str, &str)], encode: bool) -> String {
+    let mut result = String::with_capacity(to.len());
+    let mut rest = to;
+    while let Some(start) = rest.find(':') {
+        result.push_str(&rest[..start]);
+        let name_len = rest[start + 1..]
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(rest.len() - start - 1);
+        let name = &rest[start + 1..start + 1 + name_len];
+        match captures.iter().find(|(it, _)| *it == name) {
+            // captured values are decoded, and need to be encoded again in a location header
+            Some((_, value)) if encode => percent_encode(value, &mut result),
+            Some((_, value)) => result.push_str(value),
+            None => result.push_str(&rest[start..start + 1 + name_len]),
+        }
+        rest = &rest[start + 1 + name_len..];
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REDIRECTS: &str = "\
+        # legacy urls\n\
+        /old /new\n\
+        /blog/:year/:slug /posts/:slug 308\n\
+        /docs/v1/* /docs/:splat 302\n\
+        \n\
+        /app/* /app 200\n\
+        /external https://example.com/a:b 307\n\
+        /app/exact /exact 301\n";
+
+    fn redirect(status: StatusCode, location: &str) -> Option<RedirectTarget> {
+        Some(RedirectTarget::Redirect {
+            status,
+            location: location.to_string(),
+        })
+    }
+
+    #[test]
+    fn parse() {
+        let redirects = Redirects::parse(REDIRECTS, "").unwrap();
+        assert_eq!(redirects.exact.len(), 3);
+        assert_eq!(redirects.patterns.len(), 3);
+        assert_eq!(redirects.static_rewrites().collect::<Vec<_>>(), ["/app"]);
+        for invalid in [
+            "/a",
+            "/a /b 404",
+            "/a /b 301 Country=fr",
+            "a /b",
+            "/a https://example.com 200",
+            "/a/*/b /c",
+        ] {
+            assert!(Redirects::parse(invalid, "").is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn find() {
+        let redirects = Redirects::parse(REDIRECTS, "").unwrap();
+        assert_eq!(
+            redirects.find("/old"),
+            redirect(StatusCode::MovedPermanently, "/new")
+        );
+        assert_eq!(
+            redirects.find("/old/"),
+            redirect(StatusCode::MovedPermanently, "/new")
+        );
+        assert_eq!(
+            redirects.find("/blog/2020/hello"),
+            redirect(StatusCode::PermanentRedirect, "/posts/hello")
+        );
+        assert_eq!(redirects.find("/blog/2020"), None);
+        assert_eq!(redirects.find("/blog/2020/hello/world"), None);
+        assert_eq!(
+            redirects.find("/docs/v1/a/b c"),
+            redirect(StatusCode::Found, "/docs/a/b%20c")
+        );
+        assert_eq!(
+            redirects.find("/docs/v1"),
+            redirect(StatusCode::Found, "/docs/")
+        );
+        assert_eq!(
+            redirects.find("/external"),
+            redirect(StatusCode::TemporaryRedirect, "https://example.com/a:b")
+        );
+        assert_eq!(
+            redirects.find("/app/settings/42"),
+            Some(RedirectTarget::Rewrite("/app".to_string()))
+        );
+        // the pattern rule comes first
+        assert_eq!(
+            redirects.find("/app/exact"),
+            Some(RedirectTarget::Rewrite("/app".to_string()))
+        );
+        assert_eq!(redirects.find("/other"), None);
+    }
+
+    #[test]
+    fn prefix() {
+        let redirects = Redirects::parse(REDIRECTS, "/site").unwrap();
+        assert_eq!(
+            redirects.find("/site/old"),
+            redirect(StatusCode::MovedPermanently, "/site/new")
+        );
+        assert_eq!(redirects.find("/old"), None);
+        assert_eq!(
+            redirects.find("/site/external"),
+            redirect(StatusCode::TemporaryRedirect, "https://example.com/a:b")
+        );
+    }
+}
@@ -3,6 +3,8 @@ use crate::http::OwnedOrStatic;
 use crate::http::headers::Line;
 use crate::http::request::Request;
 use crate::http::response::StatusCode;
+#[cfg(feature = "swappable")]
+use crate::swappable::SwappableHandler;
 use bytes::Bytes;
 use rocket::http::uri::Path;
 use rocket::http::{Header, Status};
@@ -21,14 +23,17 @@ pub struct HandlerAdapter;
 impl RocketHandler for HandlerAdapter {
     async fn handle<'r>(&self, request: &'r RocketRequest<'_>, _data: Data<'r>) -> Outcome<'r> {
         let path = request.uri().path();
-        request
-            .rocket()
-            .state::<Arc<Handler>>()
-            .unwrap()
-            .handle(RequestAdapter {
-                inner: request,
-                path,
-            })
+        let adapter = RequestAdapter {
+            inner: request,
+            path,
+        };
+        // the managed state is either a handler or a swappable handler
+        let rocket = request.rocket();
+        #[cfg(feature = "swappable")]
+        if let Some(handler) = rocket.state::<Arc<SwappableHandler>>() {
+            return handler.load().handle(adapter);
+        }
+        rocket.state::<Arc<Handler>>().unwrap().handle(adapter)
     }
 }
 
@@ -46,6 +51,14 @@ impl<'r> Request<Outcome<'r>> for RequestAdapter<'r, '_> {
         self.path.as_bytes()
     }
 
+    fn query(&self) -> &[u8] {
+        self.inner
+            .uri()
+            .query()
+            .map(|it| it.as_str().as_bytes())
+            .unwrap_or_default()
+    }
+
     fn first_header_value(&self, key: &'static [u8]) -> Option<&[u8]> {
         from_utf8(key)
             .ok()
@@ -63,15 +76,11 @@ impl<'r> Request<Outcome<'r>> for RequestAdapter<'r, '_> {
         builder.status(Status::new(code));
         headers.for_each(|ref line| {
             let line = line.as_ref().clone();
-            builder.header(Header::new(
-                String::from_utf8_lossy(line.key),
-                match line.value {
-                    OwnedOrStatic::Owned(vec) => {
-                        Cow::Owned(String::from_utf8_lossy(&vec).to_string())
-                    }
-                    OwnedOrStatic::Static(slice) => String::from_utf8_lossy(slice),
-                },
-            ));
+            let name = match line.custom_key {
+                Some(key) => OwnedOrStatic::Owned(key),
+                None => OwnedOrStatic::Static(line.key),
+            };
+            builder.header(Header::new(to_cow(name), to_cow(line.value)));
         });
         if let Some(bytes) = body {
             let len = bytes.len();
@@ -80,3 +89,10 @@ impl<'r> Request<Outcome<'r>> for RequestAdapter<'r, '_> {
         Outcome::Success(builder.finalize())
     }
 }
+
+fn to_cow(value: OwnedOrStatic) -> Cow<'static, str> {
+    match value {
+        OwnedOrStatic::Owned(vec) => Cow::Owned(String::from_utf8_lossy(&vec).to_string()),
+        OwnedOrStatic::Static(slice) => String::from_utf8_lossy(slice),
+    }
+}
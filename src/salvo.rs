@@ -2,6 +2,8 @@ use crate::handler::Handler;
 use crate::http::headers::Line;
 use crate::http::request::Request;
 use crate::http::response::StatusCode;
+#[cfg(feature = "swappable")]
+use crate::swappable::SwappableHandler;
 use bytes::Bytes;
 use salvo::async_trait;
 use salvo::http::{HeaderMap, HeaderName, HeaderValue};
@@ -26,6 +28,10 @@ impl Request<()> for RequestAdapter<'_> {
         self.request.uri().path().as_bytes()
     }
 
+    fn query(&self) -> &[u8] {
+        self.request.uri().query().unwrap_or_default().as_bytes()
+    }
+
     fn first_header_value(&self, key: &'static [u8]) -> Option<&[u8]> {
         from_utf8(key)
             .ok()
@@ -41,7 +47,7 @@ impl Request<()> for RequestAdapter<'_> {
         let status_code: HttpStatusCode = HttpStatusCode::from_u16(code.into()).unwrap();
         let mut map = HeaderMap::new();
         headers.for_each(|line| {
-            if let Ok(name) = HeaderName::from_bytes(line.key)
+            if let Ok(name) = HeaderName::from_bytes(line.name())
                 && let Ok(value) = HeaderValue::from_bytes(line.value.as_ref())
             {
                 map.append(name, value);
@@ -70,3 +76,17 @@ impl salvo::Handler for Handler {
         self.handle(RequestAdapter { request, response });
     }
 }
+
+#[cfg(feature = "swappable")]
+#[async_trait]
+impl salvo::Handler for SwappableHandler {
+    async fn handle(
+        &self,
+        request: &mut SalvoRequest,
+        _depot: &mut Depot,
+        response: &mut SalvoResponse,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        self.load().handle(RequestAdapter { request, response });
+    }
+}
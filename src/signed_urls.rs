@@ -0,0 +1,165 @@
+use crate::base64;
+use crate::errors::Result;
+use crate::normalize::percent_encode;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::cmp::Reverse;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const EXPIRES: &[u8] = b"expires";
+const SIGNATURE: &[u8] = b"signature";
+
+/// Signed link to a path under a prefix configured with `with_signed_urls`, valid until the
+/// given time.
+/// The path is the decoded request path, including the root prefix. The returned link is the
+/// percent-encoded path with `expires` (unix time in seconds) and `signature` (url-safe base64
+/// HMAC-SHA256 of the path and expiry) query parameters.
+pub fn sign_url(key: &[u8], path: &str, expires: SystemTime) -> String {
+    let expires = unix_time(expires);
+    let signature = mac(key, path, expires).finalize().into_bytes();
+    let mut url = String::with_capacity(path.len() + 72);
+    percent_encode(path, &mut url);
+    url.push_str(&format!(
+        "?expires={expires}&signature={}",
+        base64::encode_url_safe(&signature)
+    ));
+    url
+}
+
+fn mac(key: &[u8], path: &str, expires: u64) -> Hmac<Sha256> {
+    // hmac accepts keys of any length
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(path.as_bytes());
+    mac.update(b"\n");
+    mac.update(expires.to_string().as_bytes());
+    mac
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|it| it.as_secs())
+        .unwrap_or_default()
+}
+
+// Signing keys by path prefix.
+#[derive(Default)]
+pub(crate) struct SignedUrls(Vec<(String, Vec<u8>)>);
+
+impl SignedUrls {
+    pub(crate) fn add(&mut self, prefix: String, key: Vec<u8>) {
+        self.0.retain(|(it, _)| *it != prefix);
+        self.0.push((prefix, key));
+        // the longest prefix takes precedence
+        self.0.sort_by_key(|(prefix, _)| Reverse(prefix.len()));
+    }
+
+    fn find(&self, path: &str) -> Option<&[u8]> {
+        self.0
+            .iter()
+            .find(|(prefix, _)| {
+                prefix.is_empty()
+                    || path
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .map(|(_, key)| key.as_slice())
+    }
+
+    // Whether the path is under a signed prefix.
+    pub(crate) fn is_signed(&self, path: &str) -> bool {
+        self.find(path).is_some()
+    }
+
+    pub(crate) fn validate(&self) -> Result<()> {
+        match self.0.iter().find(|(_, key)| key.is_empty()) {
+            Some((prefix, _)) => Err(format!("empty signing key for prefix: {prefix}").into()),
+            None => Ok(()),
+        }
+    }
+
+    // Whether the request is allowed: paths under a signed prefix need a valid signature that
+    // hasn't expired.
+    pub(crate) fn is_allowed(&self, path: &str, query: &[u8], now: SystemTime) -> bool {
+        let Some(key) = self.find(path) else {
+            return true;
+        };
+        let (mut expires, mut signature) = (None, None);
+        for param in query.split(|&b| b == b'&') {
+            let (name, value) = match param.iter().position(|&b| b == b'=') {
+                Some(i) => (&param[..i], &param[i + 1..]),
+                None => (param, b"".as_slice()),
+            };
+            match name {
+                EXPIRES => expires = Some(value),
+                SIGNATURE => signature = Some(value),
+                _ => {}
+            }
+        }
+        let Some(expires) = expires
+            .and_then(|it| std::str::from_utf8(it).ok())
+            .and_then(|it| it.parse::<u64>().ok())
+        else {
+            return false;
+        };
+        let Some(signature) = signature.and_then(base64::decode_url_safe) else {
+            return false;
+        };
+        expires > unix_time(now) && mac(key, path, expires).verify_slice(&signature).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::normalize::query;
+    use std::time::Duration;
+
+    const KEY: &[u8] = b"secret";
+
+    fn signed_urls() -> SignedUrls {
+        let mut signed_urls = SignedUrls::default();
+        signed_urls.add("/releases".to_string(), KEY.to_vec());
+        signed_urls
+    }
+
+    #[test]
+    fn sign() {
+        let expires = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let url = sign_url(KEY, "/releases/app 1.0.zip", expires);
+        assert!(url.starts_with("/releases/app%201.0.zip?expires=1700000000&signature="));
+        // 32 bytes, without padding
+        assert_eq!(url.len() - url.find("signature=").unwrap(), 10 + 43);
+    }
+
+    #[test]
+    fn verify() {
+        let signed_urls = signed_urls();
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let url = sign_url(KEY, "/releases/app.zip", now + Duration::from_secs(60));
+        assert!(signed_urls.is_allowed("/releases/app.zip", query(url.as_bytes()), now));
+        // expired
+        let later = now + Duration::from_secs(60);
+        assert!(!signed_urls.is_allowed("/releases/app.zip", query(url.as_bytes()), later));
+        // other path
+        assert!(!signed_urls.is_allowed("/releases/app.bin", query(url.as_bytes()), now));
+        // other key
+        let url = sign_url(b"other", "/releases/app.zip", now + Duration::from_secs(60));
+        assert!(!signed_urls.is_allowed("/releases/app.zip", query(url.as_bytes()), now));
+        // tampered expiry
+        let url = sign_url(KEY, "/releases/app.zip", now + Duration::from_secs(60))
+            .replace("expires=1700000060", "expires=1800000000");
+        assert!(!signed_urls.is_allowed("/releases/app.zip", query(url.as_bytes()), now));
+        assert!(!signed_urls.is_allowed("/releases/app.zip", b"", now));
+        assert!(!signed_urls.is_allowed("/releases/app.zip", b"expires=a&signature=", now));
+        assert!(signed_urls.is_allowed("/index.html", b"", now));
+        assert!(signed_urls.is_allowed("/releases-notes", b"", now));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(signed_urls().validate().is_ok());
+        let mut signed_urls = SignedUrls::default();
+        signed_urls.add("".to_string(), vec![]);
+        assert!(signed_urls.validate().is_err());
+    }
+}
@@ -0,0 +1,622 @@
+use crate::builder::Content;
+use crate::compression::{decompress_entry, gunzip};
+use crate::date::zip_timestamp;
+use crate::errors::Result;
+use crate::path::{filename, relative_name};
+use bytes::Bytes;
+use std::borrow::{Borrow, Cow};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::Cursor;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use zip_structs::zip_central_directory::ZipCDEntry;
+use zip_structs::zip_eocd::ZipEOCD;
+use zip_structs::zip_local_file_header::ZipLocalFileHeader;
+
+/// Content that a handler can be built from: a zip archive (`Builder::with_zip`), a tar archive
+/// (`Builder::with_tar`), a directory (`Builder::with_directory`) or layers of those
+/// (`Builder::with_layers`).
+pub trait Source: Content {
+    fn files(&self) -> Result<Box<dyn Files + '_>>;
+    /// Same as `files`, without the files under the directories for which `skip` returns true
+    /// (directory names have no trailing slash). Sources that don't walk directories can ignore it.
+    fn files_skipping(&self, _skip: &dyn Fn(&str) -> bool) -> Result<Box<dyn Files + '_>> {
+        self.files()
+    }
+}
+
+/// Files of a content source.
+pub trait Files {
+    /// Names of the files, with `/` separators, in the order they should be processed.
+    fn names(&self) -> &[String];
+    /// Reads the file with the given name.
+    fn read(&mut self, name: &str) -> Result<Option<File>>;
+    /// Index of the layer that the file with the given name comes from, for layered sources.
+    fn layer(&self, _name: &str) -> Option<usize> {
+        None
+    }
+}
+
+pub struct File {
+    /// Uncompressed content.
+    pub content: Bytes,
+    pub crc32: u32,
+    /// Modification time, in seconds since the unix epoch.
+    pub last_modified: Option<u64>,
+    /// Raw deflate stream of the content, if the source has one, which can be served with a gzip
+    /// wrapper.
+    pub deflate: Option<Bytes>,
+}
+
+impl<T: Borrow<[u8]>> Source for T {
+    fn files(&self) -> Result<Box<dyn Files + '_>> {
+        Ok(Box::new(ZipFiles::new(self.borrow())?))
+    }
+}
+
+struct ZipFiles<'a> {
+    cursor: Cursor<&'a [u8]>,
+    entries: Vec<ZipCDEntry>,
+    names: Vec<String>,
+    // position of the first entry with a given name
+    positions: HashMap<String, usize>,
+}
+
+impl<'a> ZipFiles<'a> {
+    fn new(bytes: &'a [u8]) -> Result<Self> {
+        let mut cursor = Cursor::new(bytes);
+        let directory = ZipEOCD::from_reader(&mut cursor)?;
+        let entries = ZipCDEntry::all_from_eocd(&mut cursor, &directory)?;
+        let names = entries
+            .iter()
+            .map(|it| String::from_utf8(it.file_name_raw.clone()))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let mut positions = HashMap::with_capacity(names.len());
+        for (i, name) in names.iter().enumerate() {
+            positions.entry(name.clone()).or_insert(i);
+        }
+        Ok(Self {
+            cursor,
+            entries,
+            names,
+            positions,
+        })
+    }
+}
+
+impl Files for ZipFiles<'_> {
+    fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    fn read(&mut self, name: &str) -> Result<Option<File>> {
+        let Some(entry) = self.positions.get(name).map(|&i| &self.entries[i]) else {
+            return Ok(None);
+        };
+        let zip_file_header = ZipLocalFileHeader::from_central_directory(&mut self.cursor, entry)?;
+        let crc32 = zip_file_header.crc32;
+        let deflate = (zip_file_header.compression_method == 8)
+            .then(|| Bytes::copy_from_slice(zip_file_header.compressed_data.as_ref()));
+        Ok(Some(File {
+            content: decompress_entry(zip_file_header)?,
+            crc32,
+            last_modified: zip_timestamp(entry),
+            deflate,
+        }))
+    }
+}
+
+/// Tar archive, used as a content source with `Builder::with_tar`.
+/// Gzip-compressed archives (`.tar.gz`) are detected and decompressed.
+pub struct Tar<T: Borrow<[u8]>>(T);
+
+impl<T: Borrow<[u8]>> Tar<T> {
+    pub fn new(content: T) -> Self {
+        Self(content)
+    }
+}
+
+impl<T: Borrow<[u8]>> Content for Tar<T> {}
+
+impl<T: Borrow<[u8]>> Source for Tar<T> {
+    fn files(&self) -> Result<Box<dyn Files + '_>> {
+        let bytes = self.0.borrow();
+        let bytes = if bytes.starts_with(&[0x1f, 0x8b]) {
+            Cow::Owned(gunzip(bytes)?)
+        } else {
+            Cow::Borrowed(bytes)
+        };
+        Ok(Box::new(TarFiles::new(bytes)?))
+    }
+}
+
+const BLOCK: usize = 512;
+
+struct TarFiles<'a> {
+    bytes: Cow<'a, [u8]>,
+    names: Vec<String>,
+    // content range and modification time of the last entry with a given name
+    entries: HashMap<String, (Range<usize>, Option<u64>)>,
+}
+
+impl<'a> TarFiles<'a> {
+    // Reads the headers of the archive, in the ustar format with the pax and gnu extensions for
+    // long names. Only the regular files are kept.
+    fn new(bytes: Cow<'a, [u8]>) -> Result<Self> {
+        let mut names = vec![];
+        let mut entries = HashMap::new();
+        // overrides for the next entry, from pax or gnu long name headers
+        let mut next_name = None;
+        let mut next_mtime = None;
+        let mut next_size = None;
+        let mut position = 0;
+        while position < bytes.len() {
+            let header = bytes
+                .get(position..position + BLOCK)
+                .ok_or("unexpected end of tar archive")?;
+            if header.iter().all(|&b| b == 0) {
+                break;
+            }
+            if !is_valid_checksum(header) {
+                return Err(format!("invalid tar header at offset {position}").into());
+            }
+            let size = next_size
+                .take()
+                .or_else(|| number(&header[124..136]))
+                .and_then(|it| usize::try_from(it).ok())
+                .ok_or("invalid tar entry size")?;
+            let start = position + BLOCK;
+            // the size can come from a pax record, and be larger than the archive
+            let content = start
+                .checked_add(size)
+                .and_then(|end| bytes.get(start..end))
+                .ok_or("unexpected end of tar archive")?;
+            match header[156] {
+                b'x' => {
+                    for (key, value) in pax_records(content) {
+                        match key {
+                            "path" => next_name = Some(value.to_string()),
+                            "mtime" => {
+                                next_mtime = value.split('.').next().and_then(|it| it.parse().ok())
+                            }
+                            "size" => next_size = value.parse().ok(),
+                            _ => {}
+                        }
+                    }
+                }
+                b'L' => {
+                    let end = content.iter().position(|&b| b == 0).unwrap_or(size);
+                    next_name = Some(String::from_utf8(content[..end].to_vec())?);
+                }
+                b'0' | 0 => {
+                    let name = match next_name.take() {
+                        Some(name) => name,
+                        None => header_name(header)?,
+                    };
+                    let mtime = next_mtime.take().or_else(|| number(&header[136..148]));
+                    if entries
+                        .insert(name.clone(), (start..start + size, mtime))
+                        .is_none()
+                    {
+                        names.push(name);
+                    }
+                }
+                _ => {
+                    next_name = None;
+                    next_mtime = None;
+                }
+            }
+            position = start + size.div_ceil(BLOCK) * BLOCK;
+        }
+        Ok(Self {
+            bytes,
+            names,
+            entries,
+        })
+    }
+}
+
+impl Files for TarFiles<'_> {
+    fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    fn read(&mut self, name: &str) -> Result<Option<File>> {
+        let Some((range, last_modified)) = self.entries.get(name) else {
+            return Ok(None);
+        };
+        let content = Bytes::copy_from_slice(&self.bytes[range.clone()]);
+        Ok(Some(File {
+            crc32: crc32fast::hash(&content),
+            content,
+            last_modified: *last_modified,
+            deflate: None,
+        }))
+    }
+}
+
+// Name of a ustar entry, with its prefix.
+fn header_name(header: &[u8]) -> Result<String> {
+    let field = |range: Range<usize>| {
+        let field = &header[range];
+        let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+        std::str::from_utf8(&field[..end])
+    };
+    let name = field(0..100)?;
+    let prefix = if &header[257..262] == b"ustar" {
+        field(345..500)?
+    } else {
+        ""
+    };
+    Ok(if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{prefix}/{name}")
+    })
+}
+
+// Octal number field, or base-256 number if the high bit of the first byte is set.
+fn number(field: &[u8]) -> Option<u64> {
+    if field[0] & 0x80 != 0 {
+        return field[1..]
+            .iter()
+            .try_fold(0u64, |n, &b| n.checked_mul(256).map(|n| n | b as u64));
+    }
+    let digits = std::str::from_utf8(field)
+        .ok()?
+        .trim_matches(|c: char| c == '\0' || c == ' ');
+    if digits.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(digits, 8).ok()
+}
+
+// The checksum is the sum of the bytes of the header, with the checksum field as spaces.
+fn is_valid_checksum(header: &[u8]) -> bool {
+    let sum = header
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| if (148..156).contains(&i) { b' ' } else { b } as u64)
+        .sum::<u64>();
+    number(&header[148..156]) == Some(sum)
+}
+
+// Key and value of the records of a pax extended header ("<length> <key>=<value>\n").
+fn pax_records(mut content: &[u8]) -> Vec<(&str, &str)> {
+    let mut records = vec![];
+    while let Some(space) = content.iter().position(|&b| b == b' ') {
+        let Some(len) = std::str::from_utf8(&content[..space])
+            .ok()
+            .and_then(|it| it.parse::<usize>().ok())
+            .filter(|&it| it > space + 1 && it <= content.len())
+        else {
+            break;
+        };
+        if let Ok(record) = std::str::from_utf8(&content[space + 1..len - 1])
+            && let Some((key, value)) = record.split_once('=')
+        {
+            records.push((key, value));
+        }
+        content = &content[len..];
+    }
+    records
+}
+
+// Whiteout files of a layer delete the file (or directory) with the same name without the prefix
+// from the layers below, and the opaque whiteout deletes the content of its directory.
+const WHITEOUT_PREFIX: &str = ".wh.";
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+/// Ordered list of content sources, used with `Builder::with_layers`.
+/// Each layer has the prefix of the names to serve (as with `Builder::with_zip_prefix`), and
+/// the files of a layer replace the files with the same name (relative to the prefix) in the
+/// layers below.
+/// Files can be deleted from the layers below with whiteout files: `.wh.<name>` deletes the file
+/// or directory `<name>` next to it, and `.wh..wh..opq` deletes the content of its directory.
+#[derive(Default)]
+pub struct Layers<'a>(Vec<(String, Box<dyn Source + 'a>)>);
+
+impl<'a> Layers<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds a zip archive on top of the current layers.
+    pub fn with_zip(self, zip_prefix: impl Into<String>, content: impl Borrow<[u8]> + 'a) -> Self {
+        self.with_layer(zip_prefix, content)
+    }
+    /// Adds a tar archive (optionally gzip-compressed) on top of the current layers.
+    pub fn with_tar(self, prefix: impl Into<String>, content: impl Borrow<[u8]> + 'a) -> Self {
+        self.with_layer(prefix, Tar::new(content))
+    }
+    /// Adds the files of a directory on top of the current layers.
+    pub fn with_directory(self, path: impl AsRef<Path>) -> Self {
+        self.with_layer("", Directory::new(path))
+    }
+    /// Adds a content source on top of the current layers.
+    pub fn with_layer(mut self, prefix: impl Into<String>, source: impl Source + 'a) -> Self {
+        self.0.push((prefix.into(), Box::new(source)));
+        self
+    }
+}
+
+impl Content for Layers<'_> {}
+
+impl Source for Layers<'_> {
+    fn files(&self) -> Result<Box<dyn Files + '_>> {
+        self.files_skipping(&|_| false)
+    }
+
+    fn files_skipping(&self, skip: &dyn Fn(&str) -> bool) -> Result<Box<dyn Files + '_>> {
+        let mut layers = Vec::with_capacity(self.0.len());
+        // layer and name in the layer, by name relative to the layer prefix
+        let mut merged = BTreeMap::<String, (usize, String)>::new();
+        for (i, (prefix, source)) in self.0.iter().enumerate() {
+            let files = source.files_skipping(&|directory| {
+                directory.starts_with(prefix.as_str()) && skip(relative_name(prefix, directory))
+            })?;
+            let mut names = vec![];
+            for name in files
+                .names()
+                .iter()
+                .filter(|it| it.starts_with(prefix.as_str()))
+            {
+                let relative_name = relative_name(prefix, name);
+                let filename = filename(relative_name);
+                let directory = &relative_name[..relative_name.len() - filename.len()];
+                if filename == OPAQUE_WHITEOUT {
+                    merged.retain(|it, _| !it.starts_with(directory));
+                } else if let Some(deleted) = filename.strip_prefix(WHITEOUT_PREFIX) {
+                    let deleted = format!("{directory}{deleted}");
+                    merged.retain(|it, _| {
+                        it.strip_prefix(deleted.as_str())
+                            .is_none_or(|rest| !rest.is_empty() && !rest.starts_with('/'))
+                    });
+                } else {
+                    names.push((relative_name.to_string(), name.clone()));
+                }
+            }
+            // the whiteouts only apply to the layers below
+            for (relative_name, name) in names {
+                merged.insert(relative_name, (i, name));
+            }
+            layers.push(files);
+        }
+        Ok(Box::new(LayeredFiles {
+            layers,
+            names: merged.keys().cloned().collect(),
+            merged,
+        }))
+    }
+}
+
+struct LayeredFiles<'a> {
+    layers: Vec<Box<dyn Files + 'a>>,
+    names: Vec<String>,
+    merged: BTreeMap<String, (usize, String)>,
+}
+
+impl Files for LayeredFiles<'_> {
+    fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    fn read(&mut self, name: &str) -> Result<Option<File>> {
+        match self.merged.get(name) {
+            Some((layer, name)) => self.layers[*layer].read(name),
+            None => Ok(None),
+        }
+    }
+
+    fn layer(&self, name: &str) -> Option<usize> {
+        self.merged.get(name).map(|(layer, _)| *layer)
+    }
+}
+
+/// Directory on the filesystem, used as a content source with `Builder::with_directory`.
+/// The files are named by their path relative to the directory, and symbolic links are
+/// followed, except those to a directory that contains them.
+pub struct Directory(PathBuf);
+
+impl Directory {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self(path.as_ref().to_path_buf())
+    }
+}
+
+impl Content for Directory {}
+
+impl Source for Directory {
+    fn files(&self) -> Result<Box<dyn Files + '_>> {
+        self.files_skipping(&|_| false)
+    }
+
+    fn files_skipping(&self, skip: &dyn Fn(&str) -> bool) -> Result<Box<dyn Files + '_>> {
+        let mut names = vec![];
+        walk(&self.0, "", skip, &mut vec![], &mut names)?;
+        names.sort_unstable();
+        Ok(Box::new(DirectoryFiles {
+            root: &self.0,
+            names,
+        }))
+    }
+}
+
+// Adds the names of the files under the directory to the list, prefixed with the parent names.
+// The skipped directories aren't walked, and neither are the symbolic links to one of the
+// ancestors (canonical paths of the directories being walked), which would loop.
+fn walk(
+    directory: &Path,
+    parent: &str,
+    skip: &dyn Fn(&str) -> bool,
+    ancestors: &mut Vec<PathBuf>,
+    names: &mut Vec<String>,
+) -> Result<()> {
+    ancestors.push(fs::canonicalize(directory)?);
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let filename = entry
+            .file_name()
+            .into_string()
+            .map_err(|it| format!("invalid file name: {}", it.to_string_lossy()))?;
+        let name = format!("{parent}{filename}");
+        let metadata = fs::metadata(entry.path())?;
+        if metadata.is_dir() {
+            if skip(&name)
+                || entry.file_type()?.is_symlink()
+                    && ancestors.contains(&fs::canonicalize(entry.path())?)
+            {
+                continue;
+            }
+            walk(&entry.path(), &format!("{name}/"), skip, ancestors, names)?;
+        } else if metadata.is_file() {
+            names.push(name);
+        }
+    }
+    ancestors.pop();
+    Ok(())
+}
+
+struct DirectoryFiles<'a> {
+    root: &'a Path,
+    names: Vec<String>,
+}
+
+impl Files for DirectoryFiles<'_> {
+    fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    fn read(&mut self, name: &str) -> Result<Option<File>> {
+        if self
+            .names
+            .binary_search_by(|it| it.as_str().cmp(name))
+            .is_err()
+        {
+            return Ok(None);
+        }
+        let path = self.root.join(name);
+        let content = Bytes::from(fs::read(&path)?);
+        let last_modified = fs::metadata(&path)?
+            .modified()
+            .ok()
+            .and_then(|it| it.duration_since(UNIX_EPOCH).ok())
+            .map(|it| it.as_secs());
+        Ok(Some(File {
+            crc32: crc32fast::hash(&content),
+            content,
+            last_modified,
+            deflate: None,
+        }))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // mtime: 2020-01-01T00:00:00Z
+    const MTIME: u64 = 1_577_836_800;
+
+    pub(crate) fn tar_header(name: &str, size: usize, typeflag: u8) -> [u8; BLOCK] {
+        let mut header = [0u8; BLOCK];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..108].copy_from_slice(b"0000644\0");
+        header[124..136].copy_from_slice(format!("{size:011o}\0").as_bytes());
+        header[136..148].copy_from_slice(format!("{MTIME:011o}\0").as_bytes());
+        header[156] = typeflag;
+        // magic (ustar and a nul byte) and version (00)
+        header[257..265].copy_from_slice(b"ustar\x0000");
+        header[148..156].copy_from_slice(b"        ");
+        let sum = header.iter().map(|&b| b as u32).sum::<u32>();
+        header[148..156].copy_from_slice(format!("{sum:06o}\0 ").as_bytes());
+        header
+    }
+
+    fn append(tar: &mut Vec<u8>, name: &str, typeflag: u8, content: &[u8]) {
+        tar.extend_from_slice(&tar_header(name, content.len(), typeflag));
+        tar.extend_from_slice(content);
+        tar.resize(tar.len().div_ceil(BLOCK) * BLOCK, 0);
+    }
+
+    #[test]
+    fn tar_entries() {
+        let long_name = format!("{}/index.html", "a".repeat(120));
+        // the length of a record includes the length of its length
+        let pax = format!("{} path={long_name}\n13 mtime=1.5\n", 10 + long_name.len());
+        let mut tar = vec![];
+        append(&mut tar, "dir/", b'5', b"");
+        append(&mut tar, "dir/a.txt", b'0', b"a");
+        append(
+            &mut tar,
+            "././@LongLink",
+            b'L',
+            format!("{long_name}\0").as_bytes(),
+        );
+        append(&mut tar, "truncated", b'0', b"gnu");
+        append(&mut tar, "PaxHeaders/x", b'x', pax.as_bytes());
+        append(&mut tar, "truncated", b'0', b"pax");
+        append(&mut tar, "link", b'2', b"");
+        append(&mut tar, "dir/a.txt", b'0', b"b");
+        tar.resize(tar.len() + 2 * BLOCK, 0);
+        let mut files = TarFiles::new(Cow::Borrowed(&tar)).unwrap();
+        assert_eq!(files.names(), ["dir/a.txt".to_string(), long_name.clone()]);
+        // the last entry with a name replaces the previous ones
+        let file = files.read("dir/a.txt").unwrap().unwrap();
+        assert_eq!(file.content.as_ref(), b"b");
+        assert_eq!(file.crc32, crc32fast::hash(b"b"));
+        assert_eq!(file.last_modified, Some(MTIME));
+        let file = files.read(&long_name).unwrap().unwrap();
+        assert_eq!(file.content.as_ref(), b"pax");
+        assert_eq!(file.last_modified, Some(1));
+        assert!(files.read("link").unwrap().is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn directory_walk() {
+        let root = std::env::temp_dir().join(format!("zip_static_walk_{}", std::process::id()));
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::create_dir_all(root.join("node_modules/.cache")).unwrap();
+        fs::write(root.join("a/b/index.html"), "a").unwrap();
+        fs::write(root.join("node_modules/.cache/c"), "c").unwrap();
+        fs::write(root.join("node_modules/d.js"), "d").unwrap();
+        // loops to the root and to a parent, and a link to a sibling that is walked again
+        std::os::unix::fs::symlink(&root, root.join("a/b/root")).unwrap();
+        std::os::unix::fs::symlink("..", root.join("a/b/parent")).unwrap();
+        std::os::unix::fs::symlink("a", root.join("e")).unwrap();
+        let directory = Directory::new(&root);
+        let names = directory
+            .files_skipping(&|it| it == "node_modules/.cache")
+            .map(|it| it.names().to_vec());
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            names.unwrap(),
+            ["a/b/index.html", "e/b/index.html", "node_modules/d.js"]
+        );
+    }
+
+    #[test]
+    fn invalid_tar() {
+        let mut tar = tar_header("a.txt", 10, b'0').to_vec();
+        assert!(TarFiles::new(Cow::Borrowed(&tar)).is_err());
+        tar[0] = b'b';
+        tar.resize(2 * BLOCK, 0);
+        assert!(TarFiles::new(Cow::Borrowed(&tar)).is_err());
+        // sizes that overflow
+        for size in [u64::MAX, usize::MAX as u64 - BLOCK as u64 + 1] {
+            let pax = format!("{} size={size}\n", 9 + size.to_string().len());
+            let mut tar = vec![];
+            append(&mut tar, "PaxHeaders/a.txt", b'x', pax.as_bytes());
+            append(&mut tar, "a.txt", b'0', b"a");
+            assert_eq!(
+                TarFiles::new(Cow::Borrowed(&tar))
+                    .err()
+                    .unwrap()
+                    .to_string(),
+                "unexpected end of tar archive"
+            );
+        }
+    }
+}
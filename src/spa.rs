@@ -0,0 +1,131 @@
+use crate::builder::sanitize_prefix;
+use crate::http::headers::{Line, VARY};
+use std::cmp::Reverse;
+
+/// Missing paths that are served with the index page of a single page application.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FallbackRule {
+    /// Paths without a file extension in their last segment.
+    Extensionless,
+    /// Paths without a file extension in their last segment, for requests that accept `text/html`.
+    #[default]
+    ExtensionlessHtml,
+    /// All missing paths.
+    Any,
+}
+
+/// Single page application served under a path prefix.
+#[derive(Clone, Debug)]
+pub struct SpaFallback {
+    root: String,
+    index: String,
+    rule: FallbackRule,
+}
+
+impl SpaFallback {
+    /// `root` is the path prefix of the application and `index` the path of the page
+    /// served for missing paths under that prefix (`/app` and `/app` for `app/index.html`).
+    /// Both include the root prefix.
+    pub fn new(root: impl Into<String>, index: impl Into<String>) -> Self {
+        Self {
+            root: root.into(),
+            index: index.into(),
+            rule: FallbackRule::default(),
+        }
+    }
+    pub fn with_rule(mut self, rule: FallbackRule) -> Self {
+        self.rule = rule;
+        self
+    }
+    pub(crate) fn index(&self) -> &str {
+        &self.index
+    }
+    // Headers added to the responses served with the index page.
+    pub(crate) fn headers(&self) -> Option<Line> {
+        match self.rule {
+            FallbackRule::ExtensionlessHtml => Some(Line::with_array_ref_value(VARY, b"accept")),
+            _ => None,
+        }
+    }
+}
+
+// Spa fallbacks by path prefix.
+#[derive(Default)]
+pub(crate) struct SpaFallbacks(Vec<SpaFallback>);
+
+impl SpaFallbacks {
+    pub(crate) fn add(&mut self, fallback: SpaFallback) {
+        let root = sanitize_prefix(fallback.root);
+        self.0.retain(|it| it.root != root);
+        self.0.push(SpaFallback { root, ..fallback });
+        // the longest prefix takes precedence
+        self.0.sort_by_key(|it| Reverse(it.root.len()));
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &SpaFallback> {
+        self.0.iter()
+    }
+
+    // Fallback for a missing path, if it should be served with an index page.
+    pub(crate) fn find(&self, path: &str, accept: Option<&[u8]>) -> Option<&SpaFallback> {
+        let fallback = self.0.iter().find(|it| {
+            it.root.is_empty()
+                || path
+                    .strip_prefix(it.root.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })?;
+        let extensionless = || {
+            let segment = &path[path.rfind('/').map(|it| it + 1).unwrap_or(0)..];
+            !segment.contains('.')
+        };
+        let accepts_html = || {
+            accept.is_some_and(|accept| {
+                accept.split(|&b| b == b',').any(|it| {
+                    let media_type = it.split(|&b| b == b';').next().unwrap_or_default();
+                    media_type.trim_ascii().eq_ignore_ascii_case(b"text/html")
+                })
+            })
+        };
+        match fallback.rule {
+            FallbackRule::Any => Some(fallback),
+            FallbackRule::Extensionless if extensionless() => Some(fallback),
+            FallbackRule::ExtensionlessHtml if extensionless() && accepts_html() => Some(fallback),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACCEPT: &[u8] = b"text/html,application/xhtml+xml,*/*;q=0.8";
+
+    fn fallbacks() -> SpaFallbacks {
+        let mut fallbacks = SpaFallbacks::default();
+        fallbacks.add(SpaFallback::new("/app/", "/app/index"));
+        fallbacks.add(SpaFallback::new("admin", "/admin").with_rule(FallbackRule::Extensionless));
+        fallbacks
+    }
+
+    #[test]
+    fn extensionless_html() {
+        let fallbacks = fallbacks();
+        let index = |path, accept| fallbacks.find(path, accept).map(|it| it.index());
+        assert_eq!(index("/app/settings/42", Some(ACCEPT)), Some("/app/index"));
+        assert_eq!(index("/app", Some(ACCEPT)), Some("/app/index"));
+        assert_eq!(index("/app/main.abc.js", Some(ACCEPT)), None);
+        assert_eq!(index("/app/settings/42", Some(b"application/json")), None);
+        assert_eq!(index("/app/settings/42", None), None);
+        assert_eq!(index("/application", Some(ACCEPT)), None);
+    }
+
+    #[test]
+    fn extensionless() {
+        let fallbacks = fallbacks();
+        let index = |path, accept| fallbacks.find(path, accept).map(|it| it.index());
+        assert_eq!(index("/admin/users", None), Some("/admin"));
+        assert_eq!(index("/admin/users.json", None), None);
+        assert_eq!(index("/admin/v1.2/users", None), Some("/admin"));
+    }
+}
@@ -0,0 +1,57 @@
+use crate::builder::{Builder, CustomHeaderSelector, PathPrefix, ZipPrefix};
+use crate::errors::Result;
+use crate::handler::Handler;
+use crate::http::request::Request;
+use crate::source::Source;
+use arc_swap::{ArcSwap, Guard};
+use std::sync::Arc;
+
+/// Handler that can be replaced while it's serving requests.
+/// Requests are served by the current handler without taking a lock, and the requests that
+/// started before a replacement finish with the previous handler.
+/// The framework adapters (`handle_hyper_request`, `handle_axum_request`, ...) are available
+/// for the swappable handler as well.
+pub struct SwappableHandler(ArcSwap<Handler>);
+
+impl From<Handler> for SwappableHandler {
+    fn from(handler: Handler) -> Self {
+        Self::new(handler)
+    }
+}
+
+impl SwappableHandler {
+    pub fn new(handler: Handler) -> Self {
+        Self(ArcSwap::from_pointee(handler))
+    }
+    /// The current handler.
+    pub fn current(&self) -> Arc<Handler> {
+        self.0.load_full()
+    }
+    /// Replaces the current handler.
+    pub fn store(&self, handler: Handler) {
+        self.0.store(Arc::new(handler));
+    }
+    /// Builds a new handler, with the current one as the diff (see `Builder::with_diff`) so that
+    /// the compressed content of the unchanged entries is reused, and replaces the current handler
+    /// with it. The current handler is kept if the build fails.
+    pub fn replace<'a, Z, R, H, B>(&self, builder: Builder<'a, '_, Z, R, H, (), B>) -> Result<()>
+    where
+        Z: ZipPrefix,
+        R: PathPrefix,
+        H: CustomHeaderSelector<'a>,
+        B: Source,
+    {
+        let current = self.current();
+        let handler = builder.with_diff(&current).try_build()?;
+        self.store(handler);
+        Ok(())
+    }
+    /// Handles the request with the current handler (see `Handler::handle`).
+    pub fn handle<Resp, Req: Request<Resp>>(&self, request: Req) -> Resp {
+        self.load().handle(request)
+    }
+
+    pub(crate) fn load(&self) -> Guard<Arc<Handler>> {
+        self.0.load()
+    }
+}
@@ -1,49 +1,198 @@
 use crate::handler::{HeaderSelector, HeadersAndCompression};
 use crate::http::headers::{
     Line, ALLOW, CACHE_CONTROL, COEP, CONTENT_LENGTH, CONTENT_TYPE, COOP, CORP, CSP, HSTS,
-    SERVICE_WORKER_ALLOWED, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS, X_XSS_PROTECTION,
+    PERMISSIONS_POLICY, REFERRER_POLICY, SERVICE_WORKER_ALLOWED, X_CONTENT_TYPE_OPTIONS,
+    X_FRAME_OPTIONS, X_XSS_PROTECTION,
 };
 use std::sync::LazyLock;
 
+/// Headers of an html page with the default security headers.
+#[deprecated(note = "use `SecurityHeaders::default().headers(true)`")]
 pub static DEFAULT_HEADERS: LazyLock<Vec<Line>> = LazyLock::new(|| {
-    let headers/*: Vec<(&'static [u8], &'static [u8])>*/ = vec![
-        (ALLOW, b"GET, HEAD".as_slice()).into(),
-        (X_CONTENT_TYPE_OPTIONS, b"nosniff".as_slice()).into(),
-        (X_FRAME_OPTIONS, b"DENY".as_slice()).into(),
-        (X_XSS_PROTECTION, b"1; mode=block".as_slice()).into(),
-        (CORP, b"same-site".as_slice()).into(),
-        (COEP, b"crendentialless".as_slice()).into(),
-        (COOP, b"same-origin".as_slice()).into(),
-        (CSP,
-         b"\
-                default-src 'self';\
-                connect-src 'self' https:;\
-                script-src 'wasm-unsafe-eval';\
-                script-src-elem 'self' 'unsafe-inline';\
-                script-src-attr 'none';\
-                worker-src 'self' blob:;\
-                style-src 'self' 'unsafe-inline';\
-                img-src 'self' data: blob:;\
-                font-src 'self' data:;\
-                frame-src 'none';\
-                object-src 'none';\
-                base-uri 'none';\
-                frame-ancestors 'none';\
-                form-action 'none'\
-            ".as_slice()).into(),
-        (HSTS, b"max-age=63072000; includeSubDomains; preload".as_slice()).into(),
-    ];
+    let mut headers: Vec<Line> = vec![(ALLOW, b"GET, HEAD, OPTIONS".as_slice()).into()];
+    headers.extend(SecurityHeaders::default().headers(true).cloned());
     headers
 });
 pub static ERROR_HEADERS: LazyLock<Vec<Line>> = LazyLock::new(|| {
     let headers/*: Vec<(&'static [u8], &'static [u8])>*/ = vec![
-        (ALLOW, b"GET, HEAD".as_slice()).into(),
+        (ALLOW, b"GET, HEAD, OPTIONS".as_slice()).into(),
         (CONTENT_LENGTH, b"0".as_slice()).into(),
         //(HSTS, b"max-age=63072000; includeSubDomains; preload".as_slice()),
     ];
     headers
 });
 
+const DEFAULT_CSP: &[u8] = b"\
+    default-src 'self';\
+    connect-src 'self' https:;\
+    script-src 'wasm-unsafe-eval';\
+    script-src-elem 'self' 'unsafe-inline';\
+    script-src-attr 'none';\
+    worker-src 'self' blob:;\
+    style-src 'self' 'unsafe-inline';\
+    img-src 'self' data: blob:;\
+    font-src 'self' data:;\
+    frame-src 'none';\
+    object-src 'none';\
+    base-uri 'none';\
+    frame-ancestors 'none';\
+    form-action 'none'\
+";
+const STRICT_CSP: &[u8] = b"\
+    default-src 'self';\
+    connect-src 'self';\
+    script-src 'self' 'wasm-unsafe-eval';\
+    script-src-attr 'none';\
+    worker-src 'self';\
+    style-src 'self';\
+    style-src-attr 'none';\
+    img-src 'self' data:;\
+    font-src 'self';\
+    frame-src 'none';\
+    object-src 'none';\
+    base-uri 'none';\
+    frame-ancestors 'none';\
+    form-action 'self';\
+    upgrade-insecure-requests\
+";
+const RELAXED_CSP: &[u8] = b"\
+    object-src 'none';\
+    base-uri 'self';\
+    frame-ancestors 'self';\
+    upgrade-insecure-requests\
+";
+const DEFAULT_PERMISSIONS_POLICY: &[u8] =
+    b"browsing-topics=(), camera=(), geolocation=(), microphone=(), payment=(), usb=()";
+const STRICT_PERMISSIONS_POLICY: &[u8] = b"accelerometer=(), browsing-topics=(), camera=(), \
+    display-capture=(), geolocation=(), gyroscope=(), hid=(), magnetometer=(), microphone=(), \
+    midi=(), payment=(), serial=(), usb=()";
+const RELAXED_PERMISSIONS_POLICY: &[u8] = b"browsing-topics=()";
+const HSTS_PRELOAD: &[u8] = b"max-age=63072000; includeSubDomains; preload";
+
+/// Security headers added by the default header selector, depending on the type of the entry.
+/// Html pages get the document headers (content security policy, framing, isolation, ...),
+/// and the other entries (scripts, styles, images, fonts, json, ...) the smaller set of
+/// resource headers.
+#[derive(Clone, Debug)]
+pub struct SecurityHeaders {
+    document: Vec<Line>,
+    resource: Vec<Line>,
+    hsts: Option<Line>,
+    hsts_on_resources: bool,
+}
+
+impl Default for SecurityHeaders {
+    /// Content security policy allowing inline scripts and styles, cross-origin isolation,
+    /// and HSTS for html pages only.
+    fn default() -> Self {
+        Self {
+            document: vec![
+                (X_CONTENT_TYPE_OPTIONS, b"nosniff").into(),
+                (X_FRAME_OPTIONS, b"DENY").into(),
+                (X_XSS_PROTECTION, b"1; mode=block").into(),
+                (CORP, b"same-site").into(),
+                (COEP, b"credentialless").into(),
+                (COOP, b"same-origin").into(),
+                (CSP, DEFAULT_CSP).into(),
+                (REFERRER_POLICY, b"strict-origin-when-cross-origin").into(),
+                (PERMISSIONS_POLICY, DEFAULT_PERMISSIONS_POLICY).into(),
+            ],
+            resource: vec![
+                (X_CONTENT_TYPE_OPTIONS, b"nosniff").into(),
+                (CORP, b"same-site").into(),
+            ],
+            hsts: Some((HSTS, HSTS_PRELOAD).into()),
+            hsts_on_resources: false,
+        }
+    }
+}
+
+impl SecurityHeaders {
+    /// Content security policy without inline scripts and styles, no referrer, most browser
+    /// features disabled, and HSTS for all entries.
+    pub fn strict() -> Self {
+        Self {
+            document: vec![
+                (X_CONTENT_TYPE_OPTIONS, b"nosniff").into(),
+                (X_FRAME_OPTIONS, b"DENY").into(),
+                (CORP, b"same-origin").into(),
+                (COEP, b"require-corp").into(),
+                (COOP, b"same-origin").into(),
+                (CSP, STRICT_CSP).into(),
+                (REFERRER_POLICY, b"no-referrer").into(),
+                (PERMISSIONS_POLICY, STRICT_PERMISSIONS_POLICY).into(),
+            ],
+            resource: vec![
+                (X_CONTENT_TYPE_OPTIONS, b"nosniff").into(),
+                (CORP, b"same-origin").into(),
+            ],
+            hsts: Some((HSTS, HSTS_PRELOAD).into()),
+            hsts_on_resources: true,
+        }
+    }
+    /// For legacy sites with third-party scripts, styles and embeds: a content security policy
+    /// that only restricts plugins, base urls and framing, no cross-origin isolation,
+    /// and HSTS (without preload) for html pages only.
+    pub fn relaxed() -> Self {
+        Self {
+            document: vec![
+                (X_CONTENT_TYPE_OPTIONS, b"nosniff").into(),
+                (X_FRAME_OPTIONS, b"SAMEORIGIN").into(),
+                (X_XSS_PROTECTION, b"1; mode=block").into(),
+                (CSP, RELAXED_CSP).into(),
+                (REFERRER_POLICY, b"strict-origin-when-cross-origin").into(),
+                (PERMISSIONS_POLICY, RELAXED_PERMISSIONS_POLICY).into(),
+            ],
+            resource: vec![(X_CONTENT_TYPE_OPTIONS, b"nosniff").into()],
+            hsts: Some((HSTS, b"max-age=31536000").into()),
+            hsts_on_resources: false,
+        }
+    }
+    /// Sets a header for html pages, replacing the header with the same name.
+    pub fn with_document_header(mut self, key: &'static [u8], value: impl Into<Vec<u8>>) -> Self {
+        self.document.retain(|it| it.key != key);
+        self.document.push(Line::with_owned_value(key, value.into()));
+        self
+    }
+    /// Sets a header for the entries that are not html pages, replacing the header with the
+    /// same name.
+    pub fn with_resource_header(mut self, key: &'static [u8], value: impl Into<Vec<u8>>) -> Self {
+        self.resource.retain(|it| it.key != key);
+        self.resource.push(Line::with_owned_value(key, value.into()));
+        self
+    }
+    /// Removes a header from both the document and resource headers.
+    pub fn without_header(mut self, key: &[u8]) -> Self {
+        self.document.retain(|it| it.key != key);
+        self.resource.retain(|it| it.key != key);
+        self
+    }
+    /// Value of the strict-transport-security header, and whether it's also sent with the
+    /// entries that are not html pages.
+    pub fn with_hsts(mut self, value: impl Into<Vec<u8>>, on_resources: bool) -> Self {
+        self.hsts = Some(Line::with_owned_value(HSTS, value.into()));
+        self.hsts_on_resources = on_resources;
+        self
+    }
+    /// Doesn't send the strict-transport-security header.
+    pub fn without_hsts(mut self) -> Self {
+        self.hsts = None;
+        self
+    }
+    /// Security headers for an html page, or for another type of entry.
+    pub fn headers(&self, is_document: bool) -> impl Iterator<Item = &Line> {
+        let (headers, hsts) = if is_document {
+            (&self.document, self.hsts.as_ref())
+        } else {
+            (
+                &self.resource,
+                self.hsts.as_ref().filter(|_| self.hsts_on_resources),
+            )
+        };
+        headers.iter().chain(hsts)
+    }
+}
+
 const CACHE_CONTROL_NO_CACHE: &[u8] = b"public,no-cache,max-age=0,stale-if-error=3600";
 const CACHE_CONTROL_REVALIDATE: &[u8] = b"public,max-age=3600,stale-if-error=3600";
 const CACHE_CONTROL_DEFAULT: &[u8] =
@@ -51,10 +200,6 @@ const CACHE_CONTROL_DEFAULT: &[u8] =
 const CACHE_CONTROL_IMMUTABLE: &[u8] =
     b"public,max-age=86400,immutable,stale-while-revalidate=864000,stale-if-error=3600";
 
-pub(crate) fn default_headers() -> impl Iterator<Item = &'static Line> {
-    DEFAULT_HEADERS.iter()
-}
-
 pub(crate) fn default_error_headers() -> &'static [Line] {
     ERROR_HEADERS.as_slice()
 }
@@ -138,6 +283,11 @@ pub(crate) fn headers_for_type(filename: &str, extension: &str) -> Option<Header
                 )
             },
         ),
+        "apple-app-site-association" => Some(headers_and_compression(
+            Some(b"application/json"),
+            Some(CACHE_CONTROL_REVALIDATE),
+            true,
+        )),
         "ldjson" => Some(headers_and_compression(
             Some(b"application/ld+json"),
             Some(CACHE_CONTROL_DEFAULT),
@@ -293,34 +443,38 @@ fn headers_and_compression(
     cache_control: Option<&'static [u8]>,
     compressible: bool,
 ) -> HeadersAndCompression {
-    let default_headers = default_headers();
-    let mut new_headers = vec![];
+    let mut headers = vec![Line::with_slice_value(ALLOW, b"GET, HEAD, OPTIONS")];
     if let Some(content_type) = content_type {
-        new_headers.push(Line::with_slice_value(CONTENT_TYPE, content_type));
+        headers.push(Line::with_slice_value(CONTENT_TYPE, content_type));
     }
     if let Some(cache_control) = cache_control {
-        new_headers.push(Line::with_slice_value(CACHE_CONTROL, cache_control));
+        headers.push(Line::with_slice_value(CACHE_CONTROL, cache_control));
     }
     HeadersAndCompression {
-        headers: if new_headers.is_empty() {
-            default_headers.cloned().collect()
-        } else {
-            default_headers.cloned().chain(new_headers).collect()
-        },
+        headers,
         compressible,
         redirection: content_type.is_none(),
     }
 }
 
-pub(crate) struct DefaultHeaderSelector;
+pub(crate) struct DefaultHeaderSelector<'a>(pub(crate) &'a SecurityHeaders);
 
-impl HeaderSelector for DefaultHeaderSelector {
+impl HeaderSelector for DefaultHeaderSelector<'_> {
     fn headers_for_extension(
         &self,
         filename: &str,
         extension: &str,
     ) -> Option<HeadersAndCompression> {
-        headers_for_type(filename, extension)
+        let mut headers_and_compression = headers_for_type(filename, extension)?;
+        let is_document = headers_and_compression
+            .headers
+            .iter()
+            .any(|it| it.key == CONTENT_TYPE && it.value.as_ref() == b"text/html");
+        let security_headers = self.0.headers(is_document).cloned().collect::<Vec<_>>();
+        headers_and_compression
+            .headers
+            .splice(1..1, security_headers);
+        Some(headers_and_compression)
     }
     fn error_headers(&self) -> &'static [Line] {
         default_error_headers()
@@ -2,6 +2,8 @@ use crate::handler::Handler;
 use crate::http::headers::Line;
 use crate::http::request::Request;
 use crate::http::response::StatusCode;
+#[cfg(feature = "swappable")]
+use crate::swappable::SwappableHandler;
 use bytes::Bytes;
 use std::str::from_utf8;
 use xitca_http::body::ResponseBody;
@@ -17,6 +19,13 @@ impl Handler {
     }
 }
 
+#[cfg(feature = "swappable")]
+impl SwappableHandler {
+    pub fn handle_xitca_request<E>(&self, req: XitcaRequest<E>) -> Response<ResponseBody> {
+        self.load().handle_xitca_request(req)
+    }
+}
+
 struct RequestAdapter<E> {
     inner: XitcaRequest<E>,
 }
@@ -30,6 +39,10 @@ impl<E> Request<Response<ResponseBody>> for RequestAdapter<E> {
         self.inner.uri().path().as_bytes()
     }
 
+    fn query(&self) -> &[u8] {
+        self.inner.uri().query().unwrap_or_default().as_bytes()
+    }
+
     fn first_header_value(&self, key: &'static [u8]) -> Option<&[u8]> {
         from_utf8(key)
             .ok()
@@ -48,10 +61,11 @@ impl<E> Request<Response<ResponseBody>> for RequestAdapter<E> {
         );
         let headers = res.headers_mut();
         response_headers.for_each(|line| {
-            headers.append(
-                HeaderName::from_static(from_utf8(line.key).unwrap()),
-                HeaderValue::from_bytes(line.value.as_ref()).unwrap(),
-            );
+            let name = match &line.custom_key {
+                Some(key) => HeaderName::from_bytes(key).unwrap(),
+                None => HeaderName::from_static(from_utf8(line.key).unwrap()),
+            };
+            headers.append(name, HeaderValue::from_bytes(line.value.as_ref()).unwrap());
         });
         *res.status_mut() = HttpStatusCode::from_u16(code.into()).unwrap();
         res